@@ -0,0 +1,216 @@
+//! Fetching products from the product api.
+//!
+//! `GET <api>/product/<id>` responds with a json [`ProductResponse`],
+//! the product itself is a json [`ProductDef`] where mesh and scene paths are urls
+//! relative to the response url.
+//! A stand-in response is served from `assets/some_api/product/chair`.
+use std::fmt::Display;
+
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::product::{
+    definition::{AssyDef, DefinitionError, MaterialRef, ProductDef},
+    Product,
+};
+
+pub const PRODUCT_URL: &str = "https://www.example.org/some_api/product/chair";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProductResponse {
+    pub id: String,
+    pub product: ProductDef,
+}
+
+#[derive(Debug)]
+pub enum ApiError {
+    Url(url::ParseError),
+    Request(reqwest::Error),
+    Status(StatusCode),
+    Parse(serde_json::Error),
+    Definition(DefinitionError),
+}
+
+impl Display for ApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Url(e) => write!(f, "invalid api url: {e}"),
+            Self::Request(e) => write!(f, "api request failed: {e}"),
+            Self::Status(status) => write!(f, "api responded with {status}"),
+            Self::Parse(e) => write!(f, "failed to parse api response: {e}"),
+            Self::Definition(e) => write!(f, "invalid product in api response: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+impl From<url::ParseError> for ApiError {
+    fn from(e: url::ParseError) -> Self {
+        Self::Url(e)
+    }
+}
+impl From<reqwest::Error> for ApiError {
+    fn from(e: reqwest::Error) -> Self {
+        Self::Request(e)
+    }
+}
+impl From<serde_json::Error> for ApiError {
+    fn from(e: serde_json::Error) -> Self {
+        Self::Parse(e)
+    }
+}
+impl From<DefinitionError> for ApiError {
+    fn from(e: DefinitionError) -> Self {
+        Self::Definition(e)
+    }
+}
+
+impl ProductResponse {
+    pub fn parse(text: &str) -> Result<Self, ApiError> {
+        let mut response: Self = serde_json::from_str(text)?;
+        response.product = response.product.check_version()?;
+        Ok(response)
+    }
+
+    /// makes every mesh, scene, texture, mtl and library path of the product an absolute url
+    fn resolve_paths(&mut self, url: &Url) -> Result<(), ApiError> {
+        for mesh in self
+            .product
+            .parts
+            .iter_mut()
+            .filter_map(|p| p.mesh.path_mut())
+        {
+            *mesh = url.join(mesh.as_str())?.into();
+        }
+        for assy in self.product.assys.iter_mut() {
+            resolve_assy(assy, url)?;
+        }
+        let textures = self
+            .product
+            .materials
+            .iter_mut()
+            .flat_map(|c| c.materials.iter_mut())
+            .filter_map(|m| match m {
+                MaterialRef::Inline(def) => def.textures.as_mut(),
+                MaterialRef::Library(_) => None,
+            })
+            .flat_map(|t| t.paths_mut());
+        for texture in textures {
+            *texture = url.join(texture.as_str())?.into();
+        }
+        for mtl in self
+            .product
+            .materials
+            .iter_mut()
+            .filter_map(|c| c.mtl.as_mut())
+        {
+            *mtl = url.join(mtl.as_str())?.into();
+        }
+        if let Some(library) = self.product.library.as_mut() {
+            *library = url.join(library.as_str())?.into();
+        }
+        Ok(())
+    }
+}
+
+/// makes the mesh and scene paths of `assy` and its sub-assemblies absolute urls
+fn resolve_assy(assy: &mut AssyDef, url: &Url) -> Result<(), ApiError> {
+    for mesh in assy.parts.iter_mut().filter_map(|p| p.mesh.path_mut()) {
+        *mesh = url.join(mesh.as_str())?.into();
+    }
+    if let Some(scene) = assy.scene.as_mut() {
+        scene.path = url.join(scene.path.as_str())?.into();
+    }
+    for child in assy.assys.iter_mut() {
+        resolve_assy(child, url)?;
+    }
+    Ok(())
+}
+
+/// fetches and parses the product at `url`
+pub async fn fetch_product_def(url: &str) -> Result<ProductDef, ApiError> {
+    let url = Url::parse(url)?;
+    let res = reqwest::Client::new().get(url.clone()).send().await?;
+    if !res.status().is_success() {
+        return Err(ApiError::Status(res.status()));
+    }
+    let text = res.text().await?;
+    let mut response = ProductResponse::parse(&text)?;
+    response.resolve_paths(&url)?;
+    Ok(response.product)
+}
+
+/// fetches the product at `url` and loads its meshes
+pub async fn fetch_product(url: &str) -> crate::Result<Product> {
+    let def = fetch_product_def(url).await?;
+    Product::from_def(&def, "").await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::product::library::MaterialLibrary;
+
+    const RESPONSE: &str = include_str!("../assets/some_api/product/chair");
+
+    #[test]
+    fn fixture_parses() {
+        let response = ProductResponse::parse(RESPONSE).unwrap();
+        assert_eq!(response.id, "chair");
+        assert_eq!(response.product.name, "Chair (tm)");
+        assert!(!response.product.assys.is_empty());
+    }
+
+    #[test]
+    fn fixture_paths_resolve_against_response_url() {
+        let mut response = ProductResponse::parse(RESPONSE).unwrap();
+        response
+            .resolve_paths(&Url::parse(PRODUCT_URL).unwrap())
+            .unwrap();
+        let product = &response.product;
+        let meshes: Vec<&str> = product
+            .assys
+            .iter()
+            .flat_map(|a| a.walk())
+            .flat_map(|a| a.parts.iter().filter_map(|p| p.mesh.path()))
+            .collect();
+        assert!(!meshes.is_empty());
+        for mesh in meshes {
+            assert!(mesh.starts_with("https://www.example.org/chair/"), "{mesh}");
+        }
+        assert_eq!(
+            product.library.as_deref(),
+            Some("https://www.example.org/materials.toml")
+        );
+    }
+
+    #[test]
+    fn fixture_matches_chair_definition() {
+        let text = include_str!("../assets/chair/chair.toml");
+        let mut chair = ProductResponse {
+            id: "chair".into(),
+            product: ProductDef::parse("chair.toml", text).unwrap(),
+        };
+        chair
+            .resolve_paths(&Url::parse("https://www.example.org/chair/chair.toml").unwrap())
+            .unwrap();
+        let mut response = ProductResponse::parse(RESPONSE).unwrap();
+        response
+            .resolve_paths(&Url::parse(PRODUCT_URL).unwrap())
+            .unwrap();
+        assert_eq!(response, chair);
+    }
+
+    #[test]
+    fn fixture_builds_collections() {
+        let response = ProductResponse::parse(RESPONSE).unwrap();
+        let text = include_str!("../assets/materials.toml");
+        let library = MaterialLibrary::parse("materials.toml", text).unwrap();
+        for def in response.product.materials.iter() {
+            let collection = def.collection(Some(&library), Vec::new()).unwrap();
+            assert!(!collection.options().is_empty() || def.custom.is_some());
+        }
+    }
+}
@@ -0,0 +1,115 @@
+use std::fmt::Display;
+
+use crate::{
+    api::ApiError,
+    product::{
+        configuration::ConfigurationError, definition::DefinitionError, library::LibraryError,
+    },
+};
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Everything that can go wrong in cfg3demo
+#[derive(Debug)]
+pub enum Error {
+    /// downloading or deserializing an asset failed
+    Asset(three_d_asset::Error),
+    Definition(DefinitionError),
+    Library(LibraryError),
+    Api(ApiError),
+    Configuration(ConfigurationError),
+    /// an assembly needs atleast one part
+    EmptyAssy,
+    /// a product needs atleast one part or assembly
+    EmptyProduct,
+    /// a part needs atleast one material to pick from
+    NoMaterials(Box<str>),
+    /// the gpu model of a body or part was used before `init`
+    Uninitialized(Box<str>),
+    Io(std::io::Error),
+    /// a browser api failed
+    Browser(Box<str>),
+    Json(serde_json::Error),
+    #[cfg(feature = "render")]
+    Window(three_d::WindowError),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Asset(e) => write!(f, "failed to load asset: {e}"),
+            Self::Definition(e) => write!(f, "{e}"),
+            Self::Library(e) => write!(f, "{e}"),
+            Self::Api(e) => write!(f, "{e}"),
+            Self::Configuration(e) => write!(f, "{e}"),
+            Self::EmptyAssy => write!(f, "assembly must have atleast one part"),
+            Self::EmptyProduct => write!(f, "product has no parts nor assemblies"),
+            Self::NoMaterials(part) => write!(f, "part {part} has no materials to pick from"),
+            Self::Uninitialized(name) => write!(f, "model of {name} has not been initated"),
+            Self::Io(e) => write!(f, "{e}"),
+            Self::Browser(e) => write!(f, "browser error: {e}"),
+            Self::Json(e) => write!(f, "{e}"),
+            #[cfg(feature = "render")]
+            Self::Window(e) => write!(f, "failed to create window: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Asset(e) => Some(e),
+            Self::Definition(e) => Some(e),
+            Self::Library(e) => Some(e),
+            Self::Api(e) => Some(e),
+            Self::Configuration(e) => Some(e),
+            Self::Io(e) => Some(e),
+            Self::Json(e) => Some(e),
+            #[cfg(feature = "render")]
+            Self::Window(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<three_d_asset::Error> for Error {
+    fn from(e: three_d_asset::Error) -> Self {
+        Self::Asset(e)
+    }
+}
+impl From<DefinitionError> for Error {
+    fn from(e: DefinitionError) -> Self {
+        Self::Definition(e)
+    }
+}
+impl From<LibraryError> for Error {
+    fn from(e: LibraryError) -> Self {
+        Self::Library(e)
+    }
+}
+impl From<ApiError> for Error {
+    fn from(e: ApiError) -> Self {
+        Self::Api(e)
+    }
+}
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Self::Json(e)
+    }
+}
+impl From<ConfigurationError> for Error {
+    fn from(e: ConfigurationError) -> Self {
+        Self::Configuration(e)
+    }
+}
+#[cfg(feature = "render")]
+impl From<three_d::WindowError> for Error {
+    fn from(e: three_d::WindowError) -> Self {
+        Self::Window(e)
+    }
+}
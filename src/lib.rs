@@ -1,44 +1,70 @@
+pub mod api;
+mod error;
 pub mod product;
+#[cfg(feature = "render")]
 pub mod render;
+pub mod save;
 
-use log::{error, info};
+pub use error::{Error, Result};
 
 // Entry for wasm
-#[cfg(target_arch = "wasm32")]
+#[cfg(all(target_arch = "wasm32", feature = "render"))]
 use wasm_bindgen::prelude::*;
 
-#[cfg(target_arch = "wasm32")]
+#[cfg(all(target_arch = "wasm32", feature = "render"))]
 #[wasm_bindgen(start)]
-pub async fn start() -> Result<(), JsValue> {
+pub async fn start() -> std::result::Result<(), JsValue> {
+    use log::{error, info};
     use product::Product;
 
     console_log::init_with_level(log::Level::Debug).unwrap();
 
     info!("Logging works!");
 
-    let client = reqwest::Client::new();
-    let res = client
-        .get("https://www.example.org/some_api/product/chair")
-        .send()
-        .await;
-
-    let fallback = Product::assy_dummy;
-    let product = match res {
-        Ok(v) => {
-            info!("got resonse: {:#?}", v);
-            error! {"Failed to parse api response"}; // TODO parse
-            info!("falling back to dummy product");
-            fallback()
-        }
+    let url = api_url();
+    let mut product = match api::fetch_product(&url).await {
+        Ok(product) => product,
         Err(e) => {
-            error!("failed to get part from database with err{}", e);
+            error!("failed to get product from {}: {}", url, e);
             info!("falling back to dummy product");
-            fallback()
+            Product::assy_dummy().await.map_err(to_js)?
         }
     };
 
+    if let Some(code) = query_param("config") {
+        if let Err(e) = product.configure_code(&code) {
+            error!("failed to apply configuration {}: {}", code, e);
+        }
+    }
+
     std::panic::set_hook(Box::new(console_error_panic_hook::hook));
 
-    render::render(product.await).await;
-    Ok(())
+    render::render(product).await.map_err(to_js)
+}
+
+#[cfg(all(target_arch = "wasm32", feature = "render"))]
+fn to_js(e: Error) -> JsValue {
+    JsValue::from_str(&e.to_string())
+}
+
+/// the value of `key` in the query of the page url
+#[cfg(all(target_arch = "wasm32", feature = "render"))]
+fn query_param(key: &str) -> Option<String> {
+    let search = web_sys::window()?.location().search().ok()?;
+    url::form_urlencoded::parse(search.trim_start_matches('?').as_bytes())
+        .find(|(k, _)| k == key)
+        .map(|(_, value)| value.into_owned())
+}
+
+/// the product api url, `?api=<url>` in the page url overrides the default
+/// relative urls are resolved against the page, e.g. `?api=some_api/product/chair`
+#[cfg(all(target_arch = "wasm32", feature = "render"))]
+fn api_url() -> String {
+    let page = web_sys::window()
+        .and_then(|w| w.location().href().ok())
+        .and_then(|href| url::Url::parse(&href).ok());
+    match (page, query_param("api")) {
+        (Some(page), Some(api)) => page.join(&api).map(String::from).unwrap_or(api),
+        _ => api::PRODUCT_URL.into(),
+    }
 }
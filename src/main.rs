@@ -1,10 +1,36 @@
 // Entry point for non-wasm
 #[cfg(not(target_arch = "wasm32"))]
 #[tokio::main]
-async fn main() {
-    use cfg3demo::{product::Product, render};
-    let product = Product::assy_dummy();
-    render::render(product.await).await;
+async fn main() -> cfg3demo::Result<()> {
+    use cfg3demo::{api, product::Product, render};
+
+    // `<definition file>` or `--api <url>` selects the product, defaults to the dummy
+    // `--config <code>` restores a shared configuration
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let code = match args.iter().position(|arg| arg == "--config") {
+        Some(i) if i + 1 < args.len() => Some(args.drain(i..=i + 1).nth(1).unwrap_or_default()),
+        _ => None,
+    };
+    let loaded = match args.as_slice() {
+        [flag, url] if flag == "--api" => Some(api::fetch_product(url).await),
+        [path] => Some(Product::load(path).await),
+        _ => None,
+    };
+    let mut product = match loaded {
+        Some(Ok(product)) => product,
+        Some(Err(e)) => {
+            eprintln!("failed to load product: {}", e);
+            eprintln!("falling back to dummy product");
+            Product::assy_dummy().await?
+        }
+        None => Product::assy_dummy().await?,
+    };
+    if let Some(code) = code {
+        if let Err(e) = product.configure_code(&code) {
+            eprintln!("failed to apply configuration {}: {}", code, e);
+        }
+    }
+    render::render(product).await
 }
 
 #[cfg(target_arch = "wasm32")]
@@ -1,51 +1,209 @@
 use std::{cell::RefCell, ops::Deref, rc::Rc};
 
-use three_d::{egui::Ui, Context};
+#[cfg(feature = "render")]
+use log::warn;
+#[cfg(feature = "render")]
+use three_d::{
+    egui::{Slider, Ui},
+    Context, Mat4,
+};
 use three_d_asset::{AxisAlignedBoundingBox as AABB, TriMesh, Vector3};
 
+#[cfg(feature = "render")]
+use super::rules::{Blocked, Condition};
 use super::{
     component::Body,
     material::{Material, MaterialCollection, SharedMaterial},
+    parameter::{driven, Drive, SharedParameter},
+    price::Price,
     shape::cube,
+    transform::Transform,
 };
+use crate::Error;
 
 type SharedToggle = Rc<RefCell<bool>>;
+pub type SharedChoice = Rc<RefCell<Choice>>;
+
+/// Pick exactly one of several variants, e.g. arm styles
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Choice {
+    /// stable id rules refer to, the label if none is given
+    id: Box<str>,
+    label: Box<str>,
+    variants: Box<[Box<str>]>,
+    pub selected: usize,
+}
 
-#[derive(Clone, PartialEq, Eq)]
+impl Choice {
+    /// `selected` is clamped to the available variants
+    pub fn new(label: impl Into<Box<str>>, variants: Box<[Box<str>]>, selected: usize) -> Self {
+        let selected = selected.min(variants.len().saturating_sub(1));
+        let label: Box<str> = label.into();
+        Self {
+            id: label.clone(),
+            label,
+            variants,
+            selected,
+        }
+    }
+    pub fn with_id(mut self, id: impl Into<Box<str>>) -> Self {
+        self.id = id.into();
+        self
+    }
+    pub fn shared(self) -> SharedChoice {
+        Rc::new(RefCell::new(self))
+    }
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+    pub fn variants(&self) -> &[Box<str>] {
+        &self.variants
+    }
+    pub fn len(&self) -> usize {
+        self.variants.len()
+    }
+    /// selects the variant at `index`, returns false if it is out of range
+    pub fn select(&mut self, index: usize) -> bool {
+        if index >= self.variants.len() {
+            return false;
+        }
+        self.selected = index;
+        true
+    }
+}
+
+#[derive(Clone)]
 pub enum Include {
     MustHave,
     Optional {
+        /// stable id rules refer to, the label if none is given
+        id: Box<str>,
         label: Box<str>,
         opt_in: SharedToggle,
+        /// add-on price while opted in
+        price: Price,
     },
+    /// shown while `variant` is the selected variant of `choice`
+    Variant {
+        choice: SharedChoice,
+        variant: usize,
+    },
+}
+/// includes are the same if they share their toggle or choice,
+/// equal labels or variant indices of different options are not enough
+impl PartialEq for Include {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Include::MustHave, Include::MustHave) => true,
+            (
+                Include::Optional {
+                    id,
+                    label,
+                    opt_in,
+                    price,
+                },
+                Include::Optional {
+                    id: other_id,
+                    label: other_label,
+                    opt_in: other_opt_in,
+                    price: other_price,
+                },
+            ) => {
+                id == other_id
+                    && label == other_label
+                    && Rc::ptr_eq(opt_in, other_opt_in)
+                    && price == other_price
+            }
+            (
+                Include::Variant { choice, variant },
+                Include::Variant {
+                    choice: other_choice,
+                    variant: other_variant,
+                },
+            ) => Rc::ptr_eq(choice, other_choice) && variant == other_variant,
+            _ => false,
+        }
+    }
 }
+impl Eq for Include {}
+
 impl Include {
-    fn optinal(name: impl Into<Box<str>>, value: bool) -> Self {
+    pub fn variant(choice: &SharedChoice, variant: usize) -> Self {
+        Include::Variant {
+            choice: choice.clone(),
+            variant,
+        }
+    }
+    pub fn optinal(name: impl Into<Box<str>>, value: bool) -> Self {
+        let label: Box<str> = name.into();
         Include::Optional {
-            label: name.into(),
+            id: label.clone(),
+            label,
             opt_in: Rc::new(RefCell::new(value)),
+            price: Price::ZERO,
+        }
+    }
+    /// sets the id of an optional toggle
+    pub fn with_id(mut self, option: impl Into<Box<str>>) -> Self {
+        if let Include::Optional { id, .. } = &mut self {
+            *id = option.into();
+        }
+        self
+    }
+    /// sets the add-on price of an optional toggle
+    pub fn with_price(mut self, add_on: Price) -> Self {
+        if let Include::Optional { price, .. } = &mut self {
+            *price = add_on;
+        }
+        self
+    }
+    pub fn price(&self) -> Price {
+        match self {
+            Include::Optional { price, .. } => *price,
+            _ => Price::ZERO,
         }
     }
     fn get_toggle(&self) -> Option<(&str, &SharedToggle)> {
         match self {
-            Include::Optional { label, opt_in } => Some((label, opt_in)),
+            Include::Optional { label, opt_in, .. } => Some((label, opt_in)),
             _ => None,
         }
     }
-    fn is_show(&self) -> bool {
+    pub fn label(&self) -> Option<&str> {
+        self.get_toggle().map(|(label, _)| label)
+    }
+    /// the id of an optional toggle
+    pub fn id(&self) -> Option<&str> {
+        match self {
+            Include::Optional { id, .. } => Some(id),
+            _ => None,
+        }
+    }
+    /// sets an optional toggle, must haves are left as is
+    pub fn set(&self, value: bool) {
+        if let Some((_, toggle)) = self.get_toggle() {
+            *toggle.borrow_mut() = value;
+        }
+    }
+    pub fn is_show(&self) -> bool {
         match self {
             Include::Optional { opt_in, .. } => *opt_in.borrow(),
-            _ => true,
+            Include::Variant { choice, variant } => choice.borrow().selected == *variant,
+            Include::MustHave => true,
         }
     }
 }
 
 pub struct ConfigPart {
-    #[allow(dead_code)]
     name: Box<str>,
     body: Body,
     material: SharedMaterial,
     include: Include,
+    price: Price,
+    part_number: Box<str>,
 }
 impl From<(&str, Body, SharedMaterial, Include)> for ConfigPart {
     fn from(value: (&str, Body, SharedMaterial, Include)) -> Self {
@@ -66,105 +224,392 @@ impl ConfigPart {
             body: component,
             material,
             include,
+            price: Price::ZERO,
+            part_number: "".into(),
         }
     }
+    pub fn with_price(mut self, price: Price) -> Self {
+        self.price = price;
+        self
+    }
+    pub fn with_part_number(mut self, part_number: impl Into<Box<str>>) -> Self {
+        self.part_number = part_number.into();
+        self
+    }
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    pub fn price(&self) -> Price {
+        self.price
+    }
+    pub fn part_number(&self) -> &str {
+        &self.part_number
+    }
+    pub fn body(&self) -> &Body {
+        &self.body
+    }
+    pub fn material(&self) -> &SharedMaterial {
+        &self.material
+    }
+    pub fn include(&self) -> &Include {
+        &self.include
+    }
 }
 
+/// Parts and sub-assemblies placed together, e.g. a star base with its castors
 pub struct Assy {
     parts: Box<[ConfigPart]>,
+    children: Box<[Assy]>,
+    /// placement relative to the parent assembly
+    transform: Transform,
+    /// self and everything below it is only shown while this is
+    include: Include,
+    /// parameters moving or stretching self with everything below it
+    drives: Box<[Drive]>,
     materials: Box<[SharedMaterial]>,
     includes: Box<[Include]>,
+    choices: Box<[SharedChoice]>,
+    parameters: Box<[SharedParameter]>,
 }
 
-impl<'a> Assy {
-    pub fn new(parts: Box<[ConfigPart]>) -> Self {
-        assert!(parts.len() > 0, "Assy must have atleast one part");
+impl Assy {
+    pub fn new(parts: Box<[ConfigPart]>) -> crate::Result<Self> {
+        Self::nested(parts, [].into())
+    }
+
+    /// an assembly of `parts` and the sub-assemblies `children`
+    pub fn nested(parts: Box<[ConfigPart]>, children: Box<[Assy]>) -> crate::Result<Self> {
+        if parts.is_empty() && children.is_empty() {
+            return Err(Error::EmptyAssy);
+        }
+        if let Some(part) = parts.iter().find(|p| p.material.borrow().len() == 0) {
+            return Err(Error::NoMaterials(part.name.clone()));
+        }
+        let mut assy = Self {
+            parts,
+            children,
+            transform: Transform::IDENTITY,
+            include: Include::MustHave,
+            drives: [].into(),
+            materials: [].into(),
+            includes: [].into(),
+            choices: [].into(),
+            parameters: [].into(),
+        };
         let mut materials = Vec::new();
         let mut includes = Vec::new();
-        for p in parts.iter() {
+        let mut parameters = Vec::new();
+        for p in assy.parts.iter() {
             if !materials.contains(&p.material) {
                 materials.push(p.material.clone());
             }
-            if !includes.contains(&p.include) {
-                includes.push(p.include.clone());
+            includes.push(p.include.clone());
+            parameters.extend(p.body.drives().iter().map(|d| d.parameter().clone()));
+        }
+        for child in assy.children.iter() {
+            for material in child.materials.iter() {
+                if !materials.contains(material) {
+                    materials.push(material.clone());
+                }
             }
+            includes.extend(child.includes.iter().cloned());
+            parameters.extend(child.parameters.iter().cloned());
         }
-        Self {
-            parts,
-            materials: materials.into(),
-            includes: includes.into(),
+        assy.materials = materials.into();
+        assy.set_includes(includes);
+        assy.set_parameters(parameters);
+        Ok(assy)
+    }
+
+    /// keeps the distinct `parameters`
+    fn set_parameters(&mut self, parameters: Vec<SharedParameter>) {
+        let mut distinct: Vec<SharedParameter> = Vec::new();
+        for parameter in parameters {
+            if !distinct.iter().any(|p| Rc::ptr_eq(p, &parameter)) {
+                distinct.push(parameter);
+            }
+        }
+        self.parameters = distinct.into();
+    }
+
+    /// keeps the distinct `includes` and the choices of their variants
+    fn set_includes(&mut self, includes: Vec<Include>) {
+        let mut distinct: Vec<Include> = Vec::new();
+        let mut choices: Vec<SharedChoice> = Vec::new();
+        for include in includes {
+            if let Include::Variant { choice, .. } = &include {
+                if !choices.iter().any(|c| Rc::ptr_eq(c, choice)) {
+                    choices.push(choice.clone());
+                }
+            }
+            if !distinct.contains(&include) {
+                distinct.push(include);
+            }
         }
+        self.includes = distinct.into();
+        self.choices = choices.into();
+    }
+
+    pub fn with_transform(mut self, transform: Transform) -> Self {
+        self.transform = transform;
+        self
+    }
+
+    /// makes self optional or a variant, which also hides all parts below self
+    pub fn with_include(mut self, include: Include) -> Self {
+        let mut includes = self.includes.to_vec();
+        includes.insert(0, include.clone());
+        self.set_includes(includes);
+        self.include = include;
+        self
+    }
+
+    /// lets parameters move or stretch self with everything below it
+    pub fn with_drives(mut self, drives: Box<[Drive]>) -> Self {
+        let mut parameters: Vec<SharedParameter> =
+            drives.iter().map(|d| d.parameter().clone()).collect();
+        parameters.extend(self.parameters.iter().cloned());
+        self.set_parameters(parameters);
+        self.drives = drives;
+        self
     }
 
     pub fn len(&self) -> usize {
         self.parts.len()
     }
 
-    pub fn bbox(&self) -> AABB {
-        let mut bb = self.parts[0].body.bounding_box();
-        if self.len() == 1 {
-            bb
-        } else {
-            let boxes = self.parts.iter().map(|p| p.body.bounding_box());
-            for other_box in boxes {
-                bb.expand_with_aabb(&other_box);
-            }
-            bb
+    /// the parts of self, without those of the sub-assemblies
+    pub fn parts(&self) -> &[ConfigPart] {
+        &self.parts
+    }
+
+    pub fn children(&self) -> &[Assy] {
+        &self.children
+    }
+
+    pub fn transform(&self) -> &Transform {
+        &self.transform
+    }
+
+    /// the transform with the current parameter values applied
+    pub fn placement(&self) -> Transform {
+        driven(self.transform, &self.drives)
+    }
+
+    pub fn include(&self) -> &Include {
+        &self.include
+    }
+
+    /// the drives moving or stretching self, not the ones of its parts
+    pub fn drives(&self) -> &[Drive] {
+        &self.drives
+    }
+
+    /// whether self is shown, not taking the parent assemblies into account
+    pub fn is_show(&self) -> bool {
+        self.include.is_show()
+    }
+
+    /// the parts of self and its sub-assemblies that are currently shown
+    pub fn shown_parts(&self) -> Vec<&ConfigPart> {
+        let mut parts = Vec::new();
+        self.collect_shown(&mut parts);
+        parts
+    }
+
+    fn collect_shown<'a>(&'a self, parts: &mut Vec<&'a ConfigPart>) {
+        if !self.is_show() {
+            return;
+        }
+        parts.extend(self.parts.iter().filter(|p| p.include.is_show()));
+        for child in self.children.iter() {
+            child.collect_shown(parts);
+        }
+    }
+
+    /// the optional toggles of self and its sub-assemblies that are not hidden by an assembly,
+    /// empty if self is hidden
+    pub fn shown_toggles(&self) -> Vec<&Include> {
+        let mut toggles = Vec::new();
+        self.collect_toggles(&mut toggles);
+        toggles
+    }
+
+    fn collect_toggles<'a>(&'a self, toggles: &mut Vec<&'a Include>) {
+        if !self.is_show() {
+            return;
         }
+        let includes = std::iter::once(&self.include).chain(self.parts.iter().map(|p| &p.include));
+        toggles.extend(includes.filter(|inc| inc.label().is_some()));
+        for child in self.children.iter() {
+            child.collect_toggles(toggles);
+        }
+    }
+
+    /// the distinct material choices of self and its sub-assemblies
+    pub fn materials(&self) -> &[SharedMaterial] {
+        &self.materials
+    }
+
+    /// the distinct include options of self and its sub-assemblies
+    pub fn includes(&self) -> &[Include] {
+        &self.includes
+    }
+
+    /// the optional includes of self, must haves are left out
+    pub fn toggles(&self) -> impl Iterator<Item = &Include> {
+        self.includes.iter().filter(|inc| inc.label().is_some())
     }
 
+    /// the distinct variant choices of self and its sub-assemblies
+    pub fn choices(&self) -> &[SharedChoice] {
+        &self.choices
+    }
+
+    /// the distinct numeric parameters of self and its sub-assemblies
+    pub fn parameters(&self) -> &[SharedParameter] {
+        &self.parameters
+    }
+
+    /// whether `parameter` moves or stretches a part of self or its sub-assemblies that is shown
+    pub fn drives_shown(&self, parameter: &SharedParameter) -> bool {
+        if !self.is_show() {
+            return false;
+        }
+        let drives = |drives: &[Drive]| drives.iter().any(|d| Rc::ptr_eq(d.parameter(), parameter));
+        if drives(&self.drives[..]) && !self.shown_parts().is_empty() {
+            return true;
+        }
+        let mut parts = self.parts.iter().filter(|p| p.include.is_show());
+        parts.any(|p| drives(p.body.drives()))
+            || self.children.iter().any(|c| c.drives_shown(parameter))
+    }
+
+    /// the box around all parts and sub-assemblies, in the space of the parent assembly
+    pub fn bbox(&self) -> AABB {
+        let part_boxes = self.parts.iter().map(|p| p.body.bounding_box());
+        let child_boxes = self.children.iter().map(|c| c.bbox());
+        let bb = part_boxes
+            .chain(child_boxes)
+            .reduce(|mut bb, other| {
+                bb.expand_with_aabb(&other);
+                bb
+            })
+            .expect("assemblies have atleast one part or sub-assembly");
+        self.placement().bounding_box(bb)
+    }
+}
+
+#[cfg(feature = "render")]
+impl<'a> Assy {
     pub fn init(&mut self, ctx: &Context) {
+        self.init_in(ctx, Mat4::from_scale(1.0));
+    }
+    /// `parent` places the parent assembly in the scene
+    fn init_in(&mut self, ctx: &Context, parent: Mat4) {
+        let placement = parent * self.placement().matrix();
         for p in self.parts.iter_mut() {
-            p.body.init(ctx, p.material.borrow().current())
+            p.body.init(ctx, p.material.borrow().current(), placement)
+        }
+        for child in self.children.iter_mut() {
+            child.init_in(ctx, placement);
         }
     }
-    pub fn objects(&'a self) -> impl Iterator<Item = &'a (dyn three_d::Object + 'a)> {
-        self.parts.iter().filter_map(|part| {
-            if part.include.is_show() {
-                Some(part.body.object())
-            } else {
-                None
+    pub fn objects(&'a self) -> Box<dyn Iterator<Item = &'a (dyn three_d::Object + 'a)> + 'a> {
+        if !self.is_show() {
+            return Box::new(std::iter::empty());
+        }
+        let parts = self.parts.iter().filter_map(|part| {
+            if !part.include.is_show() {
+                return None;
+            }
+            match part.body.object() {
+                Ok(object) => Some(object),
+                Err(e) => {
+                    warn!("skipping {}: {}", part.name, e);
+                    None
+                }
             }
-        })
+        });
+        let children = self.children.iter().flat_map(|c| c.objects());
+        Box::new(parts.chain(children))
     }
     pub fn update(&mut self) {
+        self.update_in(Mat4::from_scale(1.0));
+    }
+    /// `parent` places the parent assembly in the scene
+    fn update_in(&mut self, parent: Mat4) {
+        let placement = parent * self.placement().matrix();
         for part in self.parts.iter_mut() {
-            part.body.update(part.material.borrow().current());
+            part.body
+                .update(part.material.borrow().current(), placement);
+        }
+        for child in self.children.iter_mut() {
+            child.update_in(placement);
         }
     }
-    pub fn add_material_ui(&mut self, ui: &mut Ui) {
+    pub fn add_material_ui(&mut self, ui: &mut Ui, blocked: &Blocked) {
+        let shown = self.shown_parts();
         for material_choice in self.materials.iter().filter(|m| m.borrow().len() > 1) {
+            // a material only matters while a part using it is shown
+            let in_use = shown
+                .iter()
+                .any(|p| Rc::ptr_eq(&*p.material, &**material_choice));
             ui.add_space(10.0);
-            ui.label(material_choice.borrow().label().to_string());
-            let n = material_choice.borrow().len();
-            for i in 0..n {
-                let name = material_choice.borrow()[i].to_string();
-                ui.radio_value(
-                    &mut RefCell::borrow_mut(material_choice).current_material,
-                    i,
-                    name,
-                );
-            }
+            ui.add_enabled_ui(in_use, |ui| {
+                let mut collection = RefCell::borrow_mut(material_choice);
+                ui.label(collection.label().to_string());
+                collection.add_swatches(ui, blocked);
+            });
         }
     }
-    pub fn add_configure_ui(&mut self, ui: &mut Ui) {
+    pub fn add_configure_ui(&mut self, ui: &mut Ui, blocked: &Blocked) {
         ui.add_space(10.);
-        for (name, toggle) in self.includes.iter().filter_map(|inc| inc.get_toggle()) {
-            ui.checkbox(&mut toggle.borrow_mut(), name);
+        for include in self.includes.iter() {
+            if let (Some(id), Some((name, toggle))) = (include.id(), include.get_toggle()) {
+                let condition = Condition::option(id);
+                blocked.checkbox(ui, &mut toggle.borrow_mut(), &condition, name);
+            }
+        }
+        for choice in self.choices.iter() {
+            ui.add_space(10.0);
+            let mut choice = choice.borrow_mut();
+            ui.label(choice.label().to_string());
+            for i in 0..choice.len() {
+                let condition = Condition::variant(choice.id(), i);
+                let name = choice.variants()[i].to_string();
+                blocked.radio(ui, &mut choice.selected, i, &condition, &name);
+            }
+        }
+        for parameter in self.parameters.iter() {
+            ui.add_space(10.0);
+            let mut parameter = parameter.borrow_mut();
+            ui.label(parameter.label().to_string());
+            let mut value = parameter.value();
+            let mut slider = Slider::new(&mut value, parameter.min()..=parameter.max());
+            if parameter.step() > 0.0 {
+                slider = slider.step_by(parameter.step() as f64);
+            }
+            if !parameter.unit().is_empty() {
+                slider = slider.suffix(format!(" {}", parameter.unit()));
+            }
+            if ui.add(slider).changed() {
+                parameter.set(value);
+            }
         }
     }
-    pub fn add_controls(&mut self, ui: &mut Ui) {
-        self.add_configure_ui(ui);
-        self.add_material_ui(ui);
+    pub fn add_controls(&mut self, ui: &mut Ui, blocked: &Blocked) {
+        self.add_configure_ui(ui, blocked);
+        self.add_material_ui(ui, blocked);
     }
 }
 /// placeholders
 impl Assy {
-    pub async fn placeholder_chair() -> Self {
+    pub async fn placeholder_chair() -> crate::Result<Self> {
         let metals: SharedMaterial = MaterialCollection::metals().into();
         let fabs: SharedMaterial = MaterialCollection::fabrics().into();
         let plastic: SharedMaterial = Material::black_plastic().into();
-        let shapes = Body::placeholder_chair().await;
+        let shapes = Body::placeholder_chair().await?;
         let materials = [
             plastic,
             metals.clone(),
@@ -191,7 +636,7 @@ impl Assy {
         Self::new(data)
     }
     #[allow(dead_code)]
-    pub fn dummy() -> Self {
+    pub fn dummy() -> crate::Result<Self> {
         let metals: SharedMaterial = MaterialCollection::metals().into();
         let parts = [
             ConfigPart::new(
@@ -211,3 +656,58 @@ impl Assy {
         Self::new(parts)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn part(name: &str, include: Include) -> ConfigPart {
+        let metals: SharedMaterial = MaterialCollection::metals().into();
+        ConfigPart::new(name.into(), cube(0.0, 0.0, 0.0).into(), metals, include)
+    }
+
+    #[test]
+    fn choices_sharing_labels_stay_apart() {
+        let variants: Box<[Box<str>]> = ["Short".into(), "Long".into()].into();
+        let front = Choice::new("Legs", variants.clone(), 0).shared();
+        let back = Choice::new("Legs", variants, 0).shared();
+        let parts = [
+            part("Front Short", Include::variant(&front, 0)),
+            part("Front Long", Include::variant(&front, 1)),
+            part("Back Short", Include::variant(&back, 0)),
+            part("Back Long", Include::variant(&back, 1)),
+            part("Back Long Again", Include::variant(&back, 1)),
+        ];
+        let assy = Assy::new(parts.into()).unwrap();
+        assert_eq!(assy.choices().len(), 2);
+        assert_eq!(assy.includes().len(), 4);
+    }
+
+    #[test]
+    fn parts_need_materials() {
+        let empty: SharedMaterial = MaterialCollection::new("Empty".into(), [].into()).into();
+        let seat = ConfigPart::new(
+            "Seat".into(),
+            cube(0.0, 0.0, 0.0).into(),
+            empty,
+            Include::MustHave,
+        );
+        let parts = [part("Legs", Include::MustHave), seat];
+        match Assy::new(parts.into()) {
+            Err(Error::NoMaterials(part)) => assert_eq!(&*part, "Seat"),
+            _ => panic!("a part without materials was accepted"),
+        }
+    }
+
+    #[test]
+    fn options_sharing_labels_stay_apart() {
+        let arms = Include::optinal("Arms", true);
+        let parts = [
+            part("Left Arm", arms.clone()),
+            part("Right Arm", arms),
+            part("Other Arms", Include::optinal("Arms", true)),
+        ];
+        let assy = Assy::new(parts.into()).unwrap();
+        assert_eq!(assy.toggles().count(), 2);
+    }
+}
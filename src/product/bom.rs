@@ -0,0 +1,272 @@
+//! Bill of materials for the current configuration.
+use std::fmt::Write;
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    material::MaterialCollection,
+    parameter::{sizes, Drive},
+    Assy, Product,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BomLine {
+    pub part_number: Box<str>,
+    pub name: Box<str>,
+    pub material: Box<str>,
+    /// the picked color as `#rrggbb` for custom color materials, empty otherwise
+    #[serde(default)]
+    pub color: Box<str>,
+    /// the parameters stretching the part, e.g. `Width 1600 mm`, empty if it has a fixed size
+    #[serde(default)]
+    pub size: Box<str>,
+    pub quantity: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Bom {
+    pub product: Box<str>,
+    pub lines: Vec<BomLine>,
+}
+
+impl Bom {
+    /// adds `line`, merging it with an equal part in the same material
+    fn push(&mut self, line: BomLine) {
+        let same = self.lines.iter_mut().find(|l| {
+            l.part_number == line.part_number
+                && l.name == line.name
+                && l.material == line.material
+                && l.color == line.color
+                && l.size == line.size
+        });
+        match same {
+            Some(same) => same.quantity += line.quantity,
+            None => self.lines.push(line),
+        }
+    }
+
+    /// adds the shown parts of `assy`, sized by their own drives and those of `drives`, the
+    /// drives of the assemblies above it
+    fn push_assy(&mut self, assy: &Assy, drives: &[&Drive]) {
+        if !assy.is_show() {
+            return;
+        }
+        let mut drives = drives.to_vec();
+        drives.extend(assy.drives());
+        for part in assy.parts().iter().filter(|p| p.include().is_show()) {
+            let materials = part.material().borrow();
+            self.push(BomLine {
+                part_number: part.part_number().into(),
+                name: part.name().into(),
+                material: materials.current().name().into(),
+                color: custom_color(&materials),
+                size: sizes(drives.iter().copied().chain(part.body().drives())).into(),
+                quantity: part.body().quantity() as u32,
+            });
+        }
+        for child in assy.children() {
+            self.push_assy(child, &drives);
+        }
+    }
+
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("part_number,name,material,color,size,quantity\n");
+        for line in self.lines.iter() {
+            let _ = writeln!(
+                csv,
+                "{},{},{},{},{},{}",
+                csv_field(&line.part_number),
+                csv_field(&line.name),
+                csv_field(&line.material),
+                line.color,
+                csv_field(&line.size),
+                line.quantity
+            );
+        }
+        csv
+    }
+
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(self)
+    }
+}
+
+/// quotes `field` if it contains characters special to csv
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.into()
+    }
+}
+
+/// the hex color of the current material if the customer picked it
+fn custom_color(materials: &MaterialCollection) -> Box<str> {
+    if materials.custom_selected() {
+        materials.current().hex().into()
+    } else {
+        "".into()
+    }
+}
+
+impl Product {
+    /// every included part with its current material
+    pub fn bom(&self) -> Bom {
+        let mut bom = Bom {
+            product: self.name.clone(),
+            lines: Vec::new(),
+        };
+        for part in self.parts.iter().filter(|p| p.opt_in()) {
+            bom.push(BomLine {
+                part_number: part.part_number().into(),
+                name: part.name().into(),
+                material: part.material().name().into(),
+                color: custom_color(part.materials()),
+                size: "".into(),
+                quantity: 1,
+            });
+        }
+        for assy in self.assys.iter() {
+            bom.push_assy(assy, &[]);
+        }
+        bom
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::product::{
+        material::{Material, SharedMaterial},
+        parameter::{Axis, Effect, Parameter},
+        shape::cube,
+        Body, ConfigPart, Include,
+    };
+
+    fn part(name: &str, material: &SharedMaterial, include: Include) -> ConfigPart {
+        ConfigPart::new(
+            name.into(),
+            cube(0.0, 0.0, 0.0).into(),
+            material.clone(),
+            include,
+        )
+    }
+
+    fn bom(assys: Vec<Assy>) -> Bom {
+        Product::new("Table", [].into(), assys.into()).bom()
+    }
+
+    #[test]
+    fn special_fields_are_quoted() {
+        assert_eq!(csv_field("Leg"), "Leg");
+        assert_eq!(csv_field("Leg, front"), "\"Leg, front\"");
+        assert_eq!(csv_field("18\" Leg"), "\"18\"\" Leg\"");
+        assert_eq!(csv_field("Leg\nfront"), "\"Leg\nfront\"");
+        let bom = Bom {
+            product: "Table".into(),
+            lines: vec![BomLine {
+                part_number: "T-1".into(),
+                name: "Top, oak".into(),
+                material: "Oak".into(),
+                color: "".into(),
+                size: "Width 1600 mm, Depth 800 mm".into(),
+                quantity: 1,
+            }],
+        };
+        assert_eq!(
+            bom.to_csv(),
+            "part_number,name,material,color,size,quantity\n\
+             T-1,\"Top, oak\",Oak,,\"Width 1600 mm, Depth 800 mm\",1\n"
+        );
+    }
+
+    #[test]
+    fn equal_parts_are_counted() {
+        let metals: SharedMaterial = MaterialCollection::metals().into();
+        let gold: SharedMaterial = MaterialCollection::metals().into();
+        gold.borrow_mut().select(1);
+        let parts = [
+            part("Leg", &metals, Include::MustHave),
+            part("Leg", &metals, Include::MustHave),
+            part("Leg", &gold, Include::MustHave),
+            part("Top", &metals, Include::MustHave),
+        ];
+        let bom = bom(vec![Assy::new(parts.into()).unwrap()]);
+        let lines: Vec<_> = bom.lines.iter().map(|l| (&*l.name, l.quantity)).collect();
+        assert_eq!(lines, [("Leg", 2), ("Leg", 1), ("Top", 1)]);
+        assert_ne!(bom.lines[0].material, bom.lines[1].material);
+    }
+
+    #[test]
+    fn hidden_parts_are_left_out() {
+        let metals: SharedMaterial = MaterialCollection::metals().into();
+        let shelf = Include::optinal("Shelf", false);
+        let drawer = Assy::new([part("Drawer", &metals, Include::MustHave)].into())
+            .unwrap()
+            .with_include(Include::optinal("Drawer", false));
+        let parts = [
+            part("Top", &metals, Include::MustHave),
+            part("Shelf", &metals, shelf.clone()),
+        ];
+        let table = Assy::nested(parts.into(), [drawer].into()).unwrap();
+        let hidden = Assy::new([part("Bench", &metals, Include::MustHave)].into())
+            .unwrap()
+            .with_include(Include::optinal("Bench", false));
+        let product = Product::new("Table", [].into(), [table, hidden].into());
+        let names = |bom: Bom| bom.lines.into_iter().map(|l| l.name).collect::<Vec<_>>();
+        assert_eq!(names(product.bom()), ["Top".into()]);
+
+        shelf.set(true);
+        assert_eq!(names(product.bom()), ["Top".into(), "Shelf".into()]);
+    }
+
+    #[test]
+    fn custom_colors_are_listed() {
+        let white = [255, 255, 255];
+        let coat = Material::new("Powder Coat", white, 0.1, 0.6);
+        let material: SharedMaterial = MaterialCollection::metals()
+            .with_custom(coat, [].into())
+            .into();
+        let assy = Assy::new([part("Top", &material, Include::MustHave)].into()).unwrap();
+        let product = Product::new("Table", [].into(), [assy].into());
+        assert_eq!(&*product.bom().lines[0].color, "");
+
+        material.borrow_mut().select(3);
+        material.borrow_mut().set_custom_color([255, 128, 0]);
+        let bom = product.bom();
+        assert_eq!(&*bom.lines[0].material, "Powder Coat");
+        assert_eq!(&*bom.lines[0].color, "#ff8000");
+        assert!(bom.to_csv().contains("Top,Powder Coat,#ff8000,,1"));
+    }
+
+    #[test]
+    fn assemblies_stretch_their_parts() {
+        let metals: SharedMaterial = MaterialCollection::metals().into();
+        let width = Parameter::new("Width", 1200.0, 2000.0, 1600.0)
+            .with_unit("mm")
+            .shared();
+        let depth = Parameter::new("Depth", 600.0, 900.0, 800.0)
+            .with_unit("mm")
+            .shared();
+        let top = ConfigPart::new(
+            "Top".into(),
+            Body::from(cube(0.0, 0.0, 0.0))
+                .with_drives([Drive::new(&depth, Effect::Stretch(Axis::Z))].into()),
+            metals.clone(),
+            Include::MustHave,
+        );
+        let frame = Assy::new([part("Rail", &metals, Include::MustHave)].into()).unwrap();
+        let table = Assy::nested([top].into(), [frame].into())
+            .unwrap()
+            .with_drives([Drive::new(&width, Effect::Stretch(Axis::X))].into());
+        let bom = bom(vec![table]);
+        let sizes: Vec<_> = bom.lines.iter().map(|l| (&*l.name, &*l.size)).collect();
+        assert_eq!(
+            sizes,
+            [
+                ("Top", "Width 1600 mm, Depth 800 mm"),
+                ("Rail", "Width 1600 mm")
+            ]
+        );
+    }
+}
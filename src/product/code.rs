@@ -0,0 +1,336 @@
+//! Compact, url safe codes of a [`Configuration`] to save and share it.
+//!
+//! A code is the format version, a `.` and the configuration as base64url encoded varints:
+//! the number of parts followed by their opt in state, material and custom color,
+//! then the number of assemblies followed by their includes, materials, choices
+//! custom colors and parameter values, each prefixed with their count.
+//! A custom color is a 0 without one, or a 1 followed by its red, green and blue.
+//! A parameter value is the bit pattern of its `f32`, so it is read back exactly.
+use std::str::FromStr;
+
+use super::{
+    configuration::{AssyConfig, ConfigurationError, PartConfig},
+    Configuration, Product,
+};
+
+/// version written into new codes
+pub const CODE_VERSION: u32 = 3;
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+impl Configuration {
+    pub fn to_code(&self) -> String {
+        let mut bytes = Vec::new();
+        push_varint(&mut bytes, self.parts.len());
+        for part in self.parts.iter() {
+            push_varint(&mut bytes, part.opt_in as usize);
+            push_varint(&mut bytes, part.material);
+            push_color(&mut bytes, part.color);
+        }
+        push_varint(&mut bytes, self.assys.len());
+        for assy in self.assys.iter() {
+            push_varint(&mut bytes, assy.includes.len());
+            for include in assy.includes.iter() {
+                push_varint(&mut bytes, *include as usize);
+            }
+            push_list(&mut bytes, &assy.materials);
+            push_list(&mut bytes, &assy.choices);
+            push_varint(&mut bytes, assy.colors.len());
+            for color in assy.colors.iter() {
+                push_color(&mut bytes, *color);
+            }
+            push_varint(&mut bytes, assy.parameters.len());
+            for value in assy.parameters.iter() {
+                push_varint(&mut bytes, value.to_bits() as usize);
+            }
+        }
+        format!("{}.{}", CODE_VERSION, encode_base64(&bytes))
+    }
+
+    pub fn from_code(code: &str) -> Result<Self, ConfigurationError> {
+        let (version, data) = code
+            .trim()
+            .split_once('.')
+            .ok_or(ConfigurationError::Code)?;
+        let version = version.parse().map_err(|_| ConfigurationError::Code)?;
+        if version != CODE_VERSION {
+            return Err(ConfigurationError::CodeVersion(version));
+        }
+        let bytes = decode_base64(data).ok_or(ConfigurationError::Code)?;
+        let mut reader = Reader(&bytes);
+        let mut config = Configuration::default();
+        for _ in 0..reader.count()? {
+            config.parts.push(PartConfig {
+                opt_in: reader.flag()?,
+                material: reader.varint()?,
+                color: reader.color()?,
+            });
+        }
+        for _ in 0..reader.count()? {
+            let mut assy = AssyConfig::default();
+            for _ in 0..reader.count()? {
+                assy.includes.push(reader.flag()?);
+            }
+            assy.materials = reader.list()?;
+            assy.choices = reader.list()?;
+            for _ in 0..reader.count()? {
+                assy.colors.push(reader.color()?);
+            }
+            for _ in 0..reader.count()? {
+                assy.parameters.push(reader.float()?);
+            }
+            config.assys.push(assy);
+        }
+        if !reader.0.is_empty() {
+            return Err(ConfigurationError::Code);
+        }
+        Ok(config)
+    }
+}
+
+impl FromStr for Configuration {
+    type Err = ConfigurationError;
+
+    fn from_str(code: &str) -> Result<Self, Self::Err> {
+        Self::from_code(code)
+    }
+}
+
+impl Product {
+    /// the code of the current configuration
+    pub fn code(&self) -> String {
+        self.configuration().to_code()
+    }
+
+    /// applies the configuration in `code`, nothing is changed if it does not fit
+    pub fn configure_code(&mut self, code: &str) -> Result<(), ConfigurationError> {
+        self.configure(&Configuration::from_code(code)?)
+    }
+}
+
+fn push_varint(bytes: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        bytes.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+fn push_color(bytes: &mut Vec<u8>, color: Option<[u8; 3]>) {
+    match color {
+        Some(rgb) => {
+            bytes.push(1);
+            bytes.extend(rgb);
+        }
+        None => bytes.push(0),
+    }
+}
+
+fn push_list(bytes: &mut Vec<u8>, values: &[usize]) {
+    push_varint(bytes, values.len());
+    for value in values {
+        push_varint(bytes, *value);
+    }
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl Reader<'_> {
+    fn varint(&mut self) -> Result<usize, ConfigurationError> {
+        let mut value = 0usize;
+        for shift in (0..usize::BITS).step_by(7) {
+            let (&byte, rest) = self.0.split_first().ok_or(ConfigurationError::Code)?;
+            self.0 = rest;
+            let bits = (byte & 0x7f) as usize;
+            // the last byte may only fill the bits left in a usize
+            if (bits << shift) >> shift != bits {
+                return Err(ConfigurationError::Code);
+            }
+            value |= bits << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(ConfigurationError::Code)
+    }
+
+    /// a count, which can not be larger than the remaining bytes
+    fn count(&mut self) -> Result<usize, ConfigurationError> {
+        let len = self.varint()?;
+        if len > self.0.len() {
+            return Err(ConfigurationError::Code);
+        }
+        Ok(len)
+    }
+
+    fn flag(&mut self) -> Result<bool, ConfigurationError> {
+        match self.varint()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(ConfigurationError::Code),
+        }
+    }
+
+    fn color(&mut self) -> Result<Option<[u8; 3]>, ConfigurationError> {
+        if !self.flag()? {
+            return Ok(None);
+        }
+        let data = self.0;
+        match data {
+            [r, g, b, rest @ ..] => {
+                self.0 = rest;
+                Ok(Some([*r, *g, *b]))
+            }
+            _ => Err(ConfigurationError::Code),
+        }
+    }
+
+    fn float(&mut self) -> Result<f32, ConfigurationError> {
+        let bits = u32::try_from(self.varint()?).map_err(|_| ConfigurationError::Code)?;
+        Ok(f32::from_bits(bits))
+    }
+
+    fn list(&mut self) -> Result<Vec<usize>, ConfigurationError> {
+        (0..self.count()?).map(|_| self.varint()).collect()
+    }
+}
+
+fn encode_base64(bytes: &[u8]) -> String {
+    let mut text = String::with_capacity(bytes.len() * 4 / 3 + 3);
+    for chunk in bytes.chunks(3) {
+        let b = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let n = ((b[0] as u32) << 16) | ((b[1] as u32) << 8) | b[2] as u32;
+        for i in 0..=chunk.len() {
+            text.push(BASE64[((n >> (18 - 6 * i)) & 0x3f) as usize] as char);
+        }
+    }
+    text
+}
+
+fn decode_base64(text: &str) -> Option<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(text.len() * 3 / 4);
+    for chunk in text.as_bytes().chunks(4) {
+        if chunk.len() < 2 {
+            return None;
+        }
+        let mut n = 0u32;
+        for (i, c) in chunk.iter().enumerate() {
+            let digit = BASE64.iter().position(|b| b == c)? as u32;
+            n |= digit << (18 - 6 * i);
+        }
+        for i in 0..chunk.len() - 1 {
+            bytes.push((n >> (16 - 8 * i)) as u8);
+        }
+    }
+    Some(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> Configuration {
+        Configuration {
+            parts: vec![
+                PartConfig {
+                    opt_in: true,
+                    material: 2,
+                    color: None,
+                },
+                PartConfig {
+                    opt_in: false,
+                    material: 300,
+                    color: Some([255, 128, 0]),
+                },
+            ],
+            assys: vec![AssyConfig {
+                includes: vec![true, false],
+                materials: vec![0, 1],
+                choices: vec![1],
+                colors: vec![None, Some([1, 2, 3])],
+                parameters: vec![450.0, 0.1, -2.5],
+            }],
+        }
+    }
+
+    fn code(bytes: &[u8]) -> String {
+        format!("{}.{}", CODE_VERSION, encode_base64(bytes))
+    }
+
+    #[test]
+    fn codes_are_read_back() {
+        let config = config();
+        let code = config.to_code();
+        assert!(code.starts_with("3."));
+        assert!(code[2..].bytes().all(|b| BASE64.contains(&b)));
+        assert_eq!(Configuration::from_code(&code).unwrap(), config);
+        assert_eq!(code.parse::<Configuration>().unwrap(), config);
+        let empty = Configuration::default();
+        assert_eq!(Configuration::from_code(&empty.to_code()).unwrap(), empty);
+    }
+
+    #[test]
+    fn broken_codes_are_rejected() {
+        let code = config().to_code();
+        let broken = [
+            &code[..code.len() - 2],
+            &code[..2],
+            "3",
+            "3.A",
+            "x.AAAA",
+            &format!("{}*", &code[..code.len() - 1]),
+            &format!("{}+", &code[..code.len() - 1]),
+        ];
+        for broken in broken {
+            assert!(
+                matches!(
+                    Configuration::from_code(broken),
+                    Err(ConfigurationError::Code)
+                ),
+                "{broken}"
+            );
+        }
+    }
+
+    #[test]
+    fn trailing_bytes_are_rejected() {
+        let mut bytes = decode_base64(&config().to_code()[2..]).unwrap();
+        bytes.push(0);
+        assert!(matches!(
+            Configuration::from_code(&code(&bytes)),
+            Err(ConfigurationError::Code)
+        ));
+    }
+
+    #[test]
+    fn other_versions_are_rejected() {
+        let data = &config().to_code()[2..];
+        for version in [0, 1, 2, 4] {
+            assert!(matches!(
+                Configuration::from_code(&format!("{version}.{data}")),
+                Err(ConfigurationError::CodeVersion(v)) if v == version
+            ));
+        }
+    }
+
+    #[test]
+    fn oversized_varints_are_rejected() {
+        let mut bytes = Vec::new();
+        push_varint(&mut bytes, usize::MAX);
+        assert_eq!(Reader(&bytes).varint().unwrap(), usize::MAX);
+
+        // the high bit of 2^usize::BITS does not fit, it must not be dropped to read 0 parts
+        let mut bytes = Vec::new();
+        push_varint(&mut bytes, 1 << (usize::BITS - 1));
+        *bytes.last_mut().unwrap() <<= 1;
+        assert!(Reader(&bytes).varint().is_err());
+        assert!(Configuration::from_code(&code(&[bytes, vec![0]].concat())).is_err());
+
+        let too_long = [0x80; 10].into_iter().chain([0]).collect::<Vec<u8>>();
+        assert!(Reader(&too_long).varint().is_err());
+    }
+}
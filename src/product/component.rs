@@ -1,47 +1,177 @@
-use log::{info, warn};
-use three_d::{Context, Gm, Mesh, Object, PhysicalMaterial};
+use std::borrow::Cow;
+
+use log::info;
+#[cfg(feature = "render")]
+use log::warn;
+#[cfg(feature = "render")]
+use three_d::{Context, Mat4, Object};
 use three_d_asset::{AxisAlignedBoundingBox as AABB, TriMesh, Vector3};
 
-use super::{material::Material, PbrModel};
+#[cfg(feature = "render")]
+use super::{material::Material, parameter::deformation, Model};
+use super::{
+    parameter::{deformed, deformed_aabb, driven, Drive},
+    transform::Transform,
+};
+#[cfg(feature = "render")]
+use crate::Error;
 
 pub struct Body {
     shape: TriMesh,
-    model: Option<PbrModel>,
+    transform: Transform,
+    /// placements of the copies of the shape relative to `transform`, one copy if empty
+    instances: Box<[Transform]>,
+    /// parameters moving or stretching self on top of `transform`
+    drives: Box<[Drive]>,
+    /// placement of the assemblies holding self
+    #[cfg(feature = "render")]
+    parent: Mat4,
+    /// the parameter offsets the uploaded mesh was deformed with
+    #[cfg(feature = "render")]
+    deformation: Vec<f32>,
+    #[cfg(feature = "render")]
+    model: Option<Model>,
 }
 
 impl Body {
     fn new(shape: TriMesh) -> Self {
-        Self { shape, model: None }
+        Self {
+            shape,
+            transform: Transform::IDENTITY,
+            instances: [].into(),
+            drives: [].into(),
+            #[cfg(feature = "render")]
+            parent: Mat4::from_scale(1.0),
+            #[cfg(feature = "render")]
+            deformation: Vec::new(),
+            #[cfg(feature = "render")]
+            model: None,
+        }
+    }
+
+    pub fn with_transform(mut self, transform: Transform) -> Self {
+        self.transform = transform;
+        self
+    }
+
+    /// draws the shape once per instance, e.g. the castors of a chair, with a single mesh
+    pub fn with_instances(mut self, instances: Box<[Transform]>) -> Self {
+        self.instances = instances;
+        self
+    }
+
+    pub fn with_drives(mut self, drives: Box<[Drive]>) -> Self {
+        self.drives = drives;
+        self
+    }
+
+    pub fn shape(&self) -> &TriMesh {
+        &self.shape
+    }
+
+    pub fn transform(&self) -> &Transform {
+        &self.transform
+    }
+
+    pub fn drives(&self) -> &[Drive] {
+        &self.drives
+    }
+
+    /// the shape with the regions of the drives moved to the current parameter values
+    pub fn deformed_shape(&self) -> Cow<'_, TriMesh> {
+        deformed(&self.shape, &self.drives)
+    }
+
+    /// the transform with the current parameter values applied
+    pub fn placement(&self) -> Transform {
+        driven(self.transform, &self.drives)
+    }
+
+    pub fn instances(&self) -> &[Transform] {
+        &self.instances
+    }
+
+    /// how many copies of the shape are drawn
+    pub fn quantity(&self) -> usize {
+        self.instances.len().max(1)
+    }
+
+    /// places self, the gpu model follows right away
+    pub fn set_transform(&mut self, transform: Transform) {
+        self.transform = transform;
+        #[cfg(feature = "render")]
+        if let Some(model) = self.model.as_mut() {
+            model.set_transformation(self.parent * self.placement().matrix());
+        }
     }
 
+    /// the box around the transformed shape and all instances, in the space of its assembly
     pub fn bounding_box(&self) -> AABB {
-        self.shape.compute_aabb()
+        let bb = deformed_aabb(self.shape.compute_aabb(), &self.drives);
+        let placement = self.placement();
+        let matrix = placement.matrix();
+        let boxes = self.instances.iter().map(|instance| {
+            let mut instance_bb = bb;
+            instance_bb.transform(&(matrix * instance.matrix()));
+            instance_bb
+        });
+        let instances_bb = boxes.reduce(|mut bb, other| {
+            bb.expand_with_aabb(&other);
+            bb
+        });
+        instances_bb.unwrap_or_else(|| placement.bounding_box(bb))
     }
 
     pub fn bb_center(&self) -> Vector3<f32> {
-        self.shape.compute_aabb().center()
+        self.bounding_box().center()
     }
+}
 
-    /// #panics
-    /// if self is not initated
-    pub fn object(&self) -> &dyn Object {
-        self.model.as_ref().expect("model has not been initated")
+#[cfg(feature = "render")]
+impl Body {
+    /// fails if self is not initated
+    pub fn object(&self) -> crate::Result<&dyn Object> {
+        match self.model.as_ref() {
+            Some(model) => Ok(model.object()),
+            None => Err(Error::Uninitialized("body".into())),
+        }
     }
 
-    pub fn init(&mut self, ctx: &Context, material: &Material) {
-        let material = PhysicalMaterial::new_opaque(ctx, &material.pbr());
-        let mesh = Mesh::new(ctx, &self.shape);
-        let model = Gm::new(mesh, material);
+    /// `parent` places the assembly holding self in the scene
+    pub fn init(&mut self, ctx: &Context, material: &Material, parent: Mat4) {
+        let shape = self.deformed_shape();
+        let mut model = if self.instances.is_empty() {
+            Model::new(ctx, &shape, material)
+        } else {
+            let instances: Box<[Mat4]> = self.instances.iter().map(|i| i.matrix()).collect();
+            Model::instanced(ctx, &shape, material, &instances)
+        };
+        self.deformation = deformation(&self.drives);
+        model.set_transformation(parent * self.placement().matrix());
+        self.parent = parent;
         self.model = Some(model);
     }
 
-    pub fn update(&mut self, material: &Material) {
-        let model = self.model.as_mut();
-        match model {
+    /// follows `material`, the parameters and `parent`, which may have moved
+    pub fn update(&mut self, material: &Material, parent: Mat4) {
+        self.parent = parent;
+        let transformation = parent * self.placement().matrix();
+        let deformation = deformation(&self.drives);
+        let shape = (deformation != self.deformation).then(|| deformed(&self.shape, &self.drives));
+        match self.model.as_mut() {
             Some(model) => {
-                model.material.albedo = material.rgb().into();
-                model.material.metallic = material.metallic();
-                model.material.roughness = material.roughness();
+                model.set_material(material);
+                // a material with a normal map needs tangents the mesh may not have yet
+                let shape = shape.or_else(|| {
+                    model
+                        .lacks_tangents()
+                        .then(|| deformed(&self.shape, &self.drives))
+                });
+                if let Some(shape) = shape {
+                    model.set_shape(&shape);
+                    self.deformation = deformation;
+                }
+                model.set_transformation(transformation);
             }
             None => warn!("model has not been initated, doing nothing here!"),
         }
@@ -55,7 +185,7 @@ impl From<TriMesh> for Body {
 }
 
 impl Body {
-    pub async fn placeholder_chair() -> Vec<(&'static str, Self)> {
+    pub async fn placeholder_chair() -> crate::Result<Vec<(&'static str, Self)>> {
         let info = [
             ("Platic Parts", "chair/plastics.obj"),
             ("Base Frame", "chair/skeleton.obj"),
@@ -69,17 +199,44 @@ impl Body {
             .into_iter()
             .map(|p| format!("./assets/{}", p))
             .collect();
-        let mut loaded = if let Ok(loaded) = three_d_asset::io::load_async(&paths).await {
-            info!("loaded skybox from assets");
-            loaded
-        } else {
-            panic!("failed to download the necessary assets, to enable running this example offline, place the relevant assets in a folder called 'assets' next to the three-d source")
-        };
+        let mut loaded = three_d_asset::io::load_async(&paths).await?;
+        info!("loaded chair from assets");
 
-        info.map(|(name, path)| {
-            let shape = loaded.deserialize(path).expect("failed to deserialize");
-            (name, Self::new(shape))
-        })
-        .into()
+        info.into_iter()
+            .map(|(name, path)| Ok((name, Self::new(loaded.deserialize(path)?))))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::product::{
+        parameter::{Axis, Effect, Parameter, Region},
+        shape::cube,
+    };
+
+    #[test]
+    fn bounding_box_follows_the_regions() {
+        let width = Parameter::new("Width", 1.0, 5.0, 2.0).shared();
+        let region = |cut, per_unit| {
+            let region = Region {
+                axis: Axis::X,
+                cut,
+                per_unit,
+            };
+            Drive::new(&width, Effect::Region(region))
+        };
+        let drives = [region(0.5, 0.5), region(-0.5, -0.5)];
+        let body = Body::from(cube(0.0, 0.0, 0.0)).with_drives(drives.into());
+        for value in [1.0, 2.0, 3.5, 5.0] {
+            width.borrow_mut().set(value);
+            let bb = body.bounding_box();
+            let expected = body.deformed_shape().compute_aabb();
+            assert_eq!((bb.min(), bb.max()), (expected.min(), expected.max()));
+        }
+        width.borrow_mut().set(5.0);
+        assert_eq!(body.bounding_box().max().x, 2.5);
+        assert_eq!(body.bounding_box().min().x, -2.5);
     }
 }
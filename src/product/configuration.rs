@@ -0,0 +1,426 @@
+//! The customer's choices for a [`Product`], independent of any rendering.
+use std::fmt::Display;
+
+use serde::{Deserialize, Serialize};
+
+use super::{material::MaterialCollection, Product};
+
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct PartConfig {
+    pub opt_in: bool,
+    pub material: usize,
+    /// the picked custom color, if the material collection has a custom entry
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color: Option<[u8; 3]>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct AssyConfig {
+    /// one state per optional toggle of the assembly
+    pub includes: Vec<bool>,
+    /// one material index per material choice of the assembly
+    pub materials: Vec<usize>,
+    /// one selected variant per variant choice of the assembly
+    #[serde(default)]
+    pub choices: Vec<usize>,
+    /// the picked custom color per material choice, `None` for those without a custom entry
+    #[serde(default)]
+    pub colors: Vec<Option<[u8; 3]>>,
+    /// one value per numeric parameter of the assembly
+    #[serde(default)]
+    pub parameters: Vec<f32>,
+}
+
+/// The state of every choice in a product,
+/// in the same order as `Product::parts` and `Product::assys`
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct Configuration {
+    pub parts: Vec<PartConfig>,
+    pub assys: Vec<AssyConfig>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigurationError {
+    /// the configuration was made for a product with other parts or options
+    Mismatch,
+    MaterialIndex {
+        collection: Box<str>,
+        index: usize,
+    },
+    VariantIndex {
+        choice: Box<str>,
+        index: usize,
+    },
+    /// a custom color for a collection without a custom entry or outside its palette
+    Color {
+        collection: Box<str>,
+    },
+    /// a parameter value out of range or between two steps
+    ParameterValue {
+        parameter: Box<str>,
+    },
+    /// a configuration code that could not be read
+    Code,
+    /// a configuration code of an unsupported version
+    CodeVersion(u32),
+}
+
+impl Display for ConfigurationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Mismatch => write!(f, "configuration does not match the product"),
+            Self::MaterialIndex { collection, index } => {
+                write!(
+                    f,
+                    "material collection {collection} has no material {index}"
+                )
+            }
+            Self::VariantIndex { choice, index } => {
+                write!(f, "choice {choice} has no variant {index}")
+            }
+            Self::Color { collection } => {
+                write!(
+                    f,
+                    "material collection {collection} does not allow this color"
+                )
+            }
+            Self::ParameterValue { parameter } => {
+                write!(f, "parameter {parameter} does not allow this value")
+            }
+            Self::Code => write!(f, "malformed configuration code"),
+            Self::CodeVersion(version) => {
+                write!(f, "unsupported configuration code version {version}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigurationError {}
+
+impl Product {
+    pub fn configuration(&self) -> Configuration {
+        let parts = self
+            .parts
+            .iter()
+            .map(|part| PartConfig {
+                opt_in: part.opt_in(),
+                material: part.materials().current_material,
+                color: part.materials().custom_color(),
+            })
+            .collect();
+        let assys = self
+            .assys
+            .iter()
+            .map(|assy| AssyConfig {
+                includes: assy.toggles().map(|inc| inc.is_show()).collect(),
+                materials: assy
+                    .materials()
+                    .iter()
+                    .map(|m| m.borrow().current_material)
+                    .collect(),
+                choices: assy.choices().iter().map(|c| c.borrow().selected).collect(),
+                colors: assy
+                    .materials()
+                    .iter()
+                    .map(|m| m.borrow().custom_color())
+                    .collect(),
+                parameters: assy
+                    .parameters()
+                    .iter()
+                    .map(|p| p.borrow().value())
+                    .collect(),
+            })
+            .collect();
+        Configuration { parts, assys }
+    }
+
+    /// checks that `config` fits self without changing anything
+    pub fn validate(&self, config: &Configuration) -> Result<(), ConfigurationError> {
+        if config.parts.len() != self.parts.len() || config.assys.len() != self.assys.len() {
+            return Err(ConfigurationError::Mismatch);
+        }
+        for (part, state) in self.parts.iter().zip(config.parts.iter()) {
+            check_index(
+                part.materials().label(),
+                part.materials().len(),
+                state.material,
+            )?;
+            check_color(part.materials(), state.color)?;
+        }
+        for (assy, state) in self.assys.iter().zip(config.assys.iter()) {
+            if state.includes.len() != assy.toggles().count()
+                || state.materials.len() != assy.materials().len()
+                || state.choices.len() != assy.choices().len()
+                || state.colors.len() > assy.materials().len()
+                || state.parameters.len() != assy.parameters().len()
+            {
+                return Err(ConfigurationError::Mismatch);
+            }
+            for (material, index) in assy.materials().iter().zip(state.materials.iter()) {
+                let material = material.borrow();
+                check_index(material.label(), material.len(), *index)?;
+            }
+            for (material, color) in assy.materials().iter().zip(state.colors.iter()) {
+                check_color(&material.borrow(), *color)?;
+            }
+            for (choice, index) in assy.choices().iter().zip(state.choices.iter()) {
+                let choice = choice.borrow();
+                if *index >= choice.len() {
+                    return Err(ConfigurationError::VariantIndex {
+                        choice: choice.label().into(),
+                        index: *index,
+                    });
+                }
+            }
+            for (parameter, value) in assy.parameters().iter().zip(state.parameters.iter()) {
+                let parameter = parameter.borrow();
+                if !parameter.allows(*value) {
+                    return Err(ConfigurationError::ParameterValue {
+                        parameter: parameter.label().into(),
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// applies `config` to self, nothing is changed if it does not fit
+    pub fn configure(&mut self, config: &Configuration) -> Result<(), ConfigurationError> {
+        self.validate(config)?;
+        for (part, state) in self.parts.iter_mut().zip(config.parts.iter()) {
+            part.set_opt_in(state.opt_in);
+            part.materials_mut().select(state.material);
+            if let Some(color) = state.color {
+                part.materials_mut().set_custom_color(color);
+            }
+        }
+        for (assy, state) in self.assys.iter().zip(config.assys.iter()) {
+            for (include, value) in assy.toggles().zip(state.includes.iter()) {
+                include.set(*value);
+            }
+            for (material, index) in assy.materials().iter().zip(state.materials.iter()) {
+                material.borrow_mut().select(*index);
+            }
+            for (choice, index) in assy.choices().iter().zip(state.choices.iter()) {
+                choice.borrow_mut().select(*index);
+            }
+            for (material, color) in assy.materials().iter().zip(state.colors.iter()) {
+                if let Some(color) = color {
+                    material.borrow_mut().set_custom_color(*color);
+                }
+            }
+            for (parameter, value) in assy.parameters().iter().zip(state.parameters.iter()) {
+                parameter.borrow_mut().set(*value);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// a custom color must be allowed by the collection, no color is always fine
+fn check_color(
+    collection: &MaterialCollection,
+    color: Option<[u8; 3]>,
+) -> Result<(), ConfigurationError> {
+    match color {
+        Some(rgb) if !collection.allows_color(rgb) => Err(ConfigurationError::Color {
+            collection: collection.label().into(),
+        }),
+        _ => Ok(()),
+    }
+}
+
+fn check_index(collection: &str, len: usize, index: usize) -> Result<(), ConfigurationError> {
+    if index < len {
+        Ok(())
+    } else {
+        Err(ConfigurationError::MaterialIndex {
+            collection: collection.into(),
+            index,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::product::{
+        material::{Material, SharedMaterial},
+        parameter::{Drive, Effect, Parameter},
+        shape::cube,
+        Assy, Choice, ConfigPart, Include, Part,
+    };
+
+    /// a stool with an optional cushion, a custom colored seat, a choice of legs and a height
+    fn stool() -> Product {
+        let cushion = Part::new(
+            "Cushion".into(),
+            cube(0.0, 1.0, 0.0),
+            MaterialCollection::fabrics(),
+            true,
+        )
+        .unwrap();
+        let white = [255, 255, 255];
+        let coat = Material::new("Powder Coat", white, 0.1, 0.6);
+        let seat: SharedMaterial = MaterialCollection::metals()
+            .with_custom(coat, [white, [0, 0, 0]].into())
+            .into();
+        let legs = Choice::new("Legs", ["Four".into(), "Three".into()].into(), 0).shared();
+        let height = Parameter::new("Height", 400.0, 600.0, 450.0)
+            .with_step(10.0)
+            .shared();
+        let parts = [
+            ConfigPart::new(
+                "Seat".into(),
+                cube(0.0, 0.0, 0.0).into(),
+                seat.clone(),
+                Include::MustHave,
+            ),
+            ConfigPart::new(
+                "Footrest".into(),
+                cube(0.0, -1.0, 0.0).into(),
+                seat.clone(),
+                Include::optinal("Footrest", false),
+            ),
+            ConfigPart::new(
+                "Four Legs".into(),
+                cube(0.0, -2.0, 0.0).into(),
+                seat.clone(),
+                Include::variant(&legs, 0),
+            ),
+            ConfigPart::new(
+                "Three Legs".into(),
+                cube(0.0, -2.0, 0.0).into(),
+                seat,
+                Include::variant(&legs, 1),
+            ),
+        ];
+        let drives = [Drive::new(&height, Effect::Translate([0.0, 0.001, 0.0]))];
+        let assy = Assy::new(parts.into()).unwrap().with_drives(drives.into());
+        Product::new("Stool", [cushion].into(), [assy].into())
+    }
+
+    fn changed(product: &Product) -> Configuration {
+        let mut config = product.configuration();
+        config.parts[0].opt_in = false;
+        config.parts[0].material = 1;
+        let assy = &mut config.assys[0];
+        assy.includes = vec![true];
+        assy.materials = vec![3];
+        assy.colors = vec![Some([0, 0, 0])];
+        assy.choices = vec![1];
+        assy.parameters = vec![520.0];
+        config
+    }
+
+    #[test]
+    fn configuration_describes_the_product() {
+        let product = stool();
+        let config = product.configuration();
+        assert_eq!(config.parts.len(), 1);
+        assert_eq!(config.assys.len(), 1);
+        let assy = &config.assys[0];
+        assert_eq!(assy.includes, vec![false]);
+        assert_eq!(assy.materials, vec![0]);
+        assert_eq!(assy.colors, vec![Some([255, 255, 255])]);
+        assert_eq!(assy.choices, vec![0]);
+        assert_eq!(assy.parameters, vec![450.0]);
+    }
+
+    #[test]
+    fn configure_round_trips() {
+        let mut product = stool();
+        let config = changed(&product);
+        assert_ne!(product.configuration(), config);
+        product.configure(&config).unwrap();
+        assert_eq!(product.configuration(), config);
+
+        let mut other = stool();
+        other.configure(&product.configuration()).unwrap();
+        assert_eq!(other.configuration(), config);
+    }
+
+    #[test]
+    fn mismatch() {
+        let mut product = stool();
+        let before = product.configuration();
+
+        let mut config = before.clone();
+        config.parts.push(PartConfig::default());
+        assert_eq!(
+            product.configure(&config),
+            Err(ConfigurationError::Mismatch)
+        );
+
+        let mut config = before.clone();
+        config.assys.clear();
+        assert_eq!(product.validate(&config), Err(ConfigurationError::Mismatch));
+
+        let mut config = before.clone();
+        config.assys[0].includes.push(true);
+        assert_eq!(product.validate(&config), Err(ConfigurationError::Mismatch));
+
+        let mut config = before.clone();
+        config.assys[0].parameters.clear();
+        assert_eq!(product.validate(&config), Err(ConfigurationError::Mismatch));
+        assert_eq!(product.configuration(), before);
+    }
+
+    #[test]
+    fn out_of_range() {
+        let mut product = stool();
+        let before = product.configuration();
+
+        let mut config = changed(&product);
+        config.parts[0].material = 2;
+        assert_eq!(
+            product.configure(&config),
+            Err(ConfigurationError::MaterialIndex {
+                collection: "Fabrics".into(),
+                index: 2
+            })
+        );
+        // nothing is applied when a later entry is out of range
+        assert_eq!(product.configuration(), before);
+
+        let mut config = before.clone();
+        config.assys[0].materials = vec![4];
+        assert_eq!(
+            product.validate(&config),
+            Err(ConfigurationError::MaterialIndex {
+                collection: "Metals".into(),
+                index: 4
+            })
+        );
+
+        let mut config = before.clone();
+        config.assys[0].choices = vec![2];
+        assert_eq!(
+            product.validate(&config),
+            Err(ConfigurationError::VariantIndex {
+                choice: "Legs".into(),
+                index: 2
+            })
+        );
+
+        let mut config = before.clone();
+        config.assys[0].colors = vec![Some([1, 2, 3])];
+        assert_eq!(
+            product.validate(&config),
+            Err(ConfigurationError::Color {
+                collection: "Metals".into()
+            })
+        );
+
+        for value in [390.0, 455.0, 610.0, f32::NAN] {
+            let mut config = before.clone();
+            config.assys[0].parameters = vec![value];
+            assert_eq!(
+                product.validate(&config),
+                Err(ConfigurationError::ParameterValue {
+                    parameter: "Height".into()
+                })
+            );
+        }
+    }
+}
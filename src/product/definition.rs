@@ -0,0 +1,1921 @@
+//! Declarative product definitions.
+//!
+//! A [`ProductDef`] describes a product in TOML or JSON: its material
+//! collections, optional toggles and the parts that reference them.
+//! Designers can add a new product by writing a definition file, no rust needed.
+//!
+//! ```toml
+//! version = 1
+//! name = "Chair (tm)"
+//!
+//! [[materials]]
+//! id = "metals"
+//! label = "Metals"
+//! materials = [
+//!     { name = "Aluminium", rgb = [132, 135, 137], metallic = 0.7, roughness = 0.3 },
+//!     { name = "Gold", rgb = [212, 175, 55], metallic = 0.9, roughness = 0.2 },
+//! ]
+//!
+//! [[options]]
+//! id = "arms"
+//! label = "Arms"
+//! default = true
+//!
+//! [[assys]]
+//! name = "Chair"
+//!
+//! [[assys.parts]]
+//! name = "Arm Frame"
+//! mesh = "metal_arm.obj"
+//! material = "metals"
+//! option = "arms"
+//! ```
+//!
+//! Parts can also be variants of a choice where exactly one variant is shown
+//!
+//! ```toml
+//! [[choices]]
+//! id = "base"
+//! label = "Base"
+//! variants = ["Star", "Sled"]
+//!
+//! [[assys.parts]]
+//! name = "Sled Base"
+//! mesh = "sled.obj"
+//! material = "metals"
+//! variant = { choice = "base", name = "Sled" }
+//! ```
+//!
+//! Assemblies can hold sub-assemblies, placed relative to their parent and
+//! shown or hidden with all their parts
+//!
+//! ```toml
+//! [[assys.assys]]
+//! name = "Arms"
+//! option = "arms"
+//! transform = { translation = [0.0, 0.0, 40.0] }
+//!
+//! [[assys.assys.parts]]
+//! name = "Arm Frame"
+//! mesh = "metal_arm.obj"
+//! material = "metals"
+//! ```
+//!
+//! Numeric parameters move or stretch parts and assemblies, see [`super::parameter`]
+//!
+//! Presets set options, materials, choices and parameters by id at once, see [`super::preset`]
+//!
+//! Whole assemblies can come from a glTF, GLB or OBJ file, see [`super::gltf`]
+//!
+//! Instead of a file, a part can use a generated mesh like a cylinder, see [`super::shape`]
+//!
+//! With a [material library](super::library) collections can reference library
+//! materials by id, or take all materials of a library collection
+//!
+//! ```toml
+//! library = "../materials.toml"
+//!
+//! [[materials]]
+//! id = "metals"
+//! label = "Metals"
+//! collection = "metals"
+//! materials = ["brass"]
+//! ```
+use std::collections::HashMap;
+use std::fmt::Display;
+
+use serde::{de, Deserialize, Deserializer, Serialize};
+use three_d_asset::{io::RawAssets, Scene, TriMesh};
+
+use super::{
+    assembly::{Assy, Choice, ConfigPart, Include, SharedChoice},
+    gltf::SceneDef,
+    library::{rgb_from_hex, MaterialLibrary},
+    material::{load_textures, Material, MaterialCollection, SharedMaterial, TextureMap, Textures},
+    obj::{materials_from_mtl, scene_from_obj},
+    parameter::{Drive, Effect, Parameter, SharedParameter},
+    preset::Preset,
+    price::Price,
+    rules::{Condition, Rule, RuleKind},
+    shape::Shape,
+    transform::Transform,
+    Body, Part, Product,
+};
+
+/// The definition format version this build understands
+pub const FORMAT_VERSION: u32 = 1;
+
+#[derive(Debug)]
+pub enum DefinitionError {
+    Toml(toml::de::Error),
+    Json(serde_json::Error),
+    UnknownFormat(Box<str>),
+    Version(u32),
+    UnknownMaterial {
+        part: Box<str>,
+        id: Box<str>,
+    },
+    UnknownOption {
+        part: Box<str>,
+        id: Box<str>,
+    },
+    UnknownChoice {
+        part: Box<str>,
+        id: Box<str>,
+    },
+    UnknownVariant {
+        part: Box<str>,
+        choice: Box<str>,
+        name: Box<str>,
+    },
+    /// a scene mesh has neither a material of its own nor a collection
+    NoMaterial(Box<str>),
+    /// an OBJ or MTL file has a malformed statement at `line`
+    InvalidObj {
+        path: Box<str>,
+        line: usize,
+    },
+    /// a scene configures a node the glTF file does not have
+    UnknownNode {
+        assy: Box<str>,
+        node: Box<str>,
+    },
+    /// a part transform with an infinite or NaN value or a scale that is not positive
+    InvalidTransform(Box<str>),
+    /// a region drive whose cut is outside the mesh or which shrinks the mesh past its cut
+    InvalidRegion(Box<str>),
+    /// a region drive of an assembly, which has no mesh of its own to deform
+    AssyRegion(Box<str>),
+    /// a generated mesh with dimensions that are not positive or do not fit together,
+    /// or with too few or too many segments
+    InvalidShape(Box<str>),
+    UnknownParameter {
+        part: Box<str>,
+        id: Box<str>,
+    },
+    /// a parameter whose range, step or default is not usable
+    InvalidParameter(Box<str>),
+    /// a stretch drive whose parameter can reach 0 or below, which would flatten or mirror
+    /// the part
+    InvalidStretch {
+        part: Box<str>,
+        id: Box<str>,
+    },
+    /// a part can either be an option or a variant, not both
+    ConflictingInclude(Box<str>),
+    EmptyChoice(Box<str>),
+    EmptyCollection(Box<str>),
+    EmptyAssy(Box<str>),
+    /// a collection references library materials, but the definition has no library
+    NoLibrary(Box<str>),
+    /// a collection references a material or collection the library does not have
+    UnknownLibraryEntry {
+        collection: Box<str>,
+        id: Box<str>,
+    },
+    /// a material with a NaN or out of range value, see [`Material::invalid_values`]
+    InvalidMaterial {
+        material: Box<str>,
+        value: &'static str,
+        range: &'static str,
+    },
+    /// a custom color that is not a `#rrggbb` hex color
+    InvalidColor {
+        collection: Box<str>,
+        color: Box<str>,
+    },
+    /// a rule references an option, material or variant the product does not have
+    UnknownRuleTarget {
+        rule: Box<str>,
+        target: Box<str>,
+    },
+    /// a preset references an unknown option, material or choice
+    UnknownPresetTarget {
+        preset: Box<str>,
+        target: Box<str>,
+    },
+    /// a preset configures something a rule does not allow
+    PresetBreaksRule {
+        preset: Box<str>,
+        rule: Box<str>,
+    },
+}
+
+impl Display for DefinitionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Toml(e) => write!(f, "invalid toml definition: {e}"),
+            Self::Json(e) => write!(f, "invalid json definition: {e}"),
+            Self::UnknownFormat(path) => {
+                write!(f, "unknown definition format: {path}, expected .toml or .json")
+            }
+            Self::Version(v) => write!(
+                f,
+                "unsupported definition version {v}, expected {FORMAT_VERSION}"
+            ),
+            Self::UnknownMaterial { part, id } => {
+                write!(f, "part {part} references unknown material collection {id}")
+            }
+            Self::UnknownOption { part, id } => {
+                write!(f, "part {part} references unknown option {id}")
+            }
+            Self::UnknownChoice { part, id } => {
+                write!(f, "part {part} references unknown choice {id}")
+            }
+            Self::UnknownVariant { part, choice, name } => {
+                write!(
+                    f,
+                    "part {part} references unknown variant {name} of {choice}"
+                )
+            }
+            Self::NoMaterial(part) => {
+                write!(f, "part {part} has no material, map it to a collection")
+            }
+            Self::InvalidObj { path, line } => write!(f, "invalid {path} at line {line}"),
+            Self::UnknownNode { assy, node } => {
+                write!(
+                    f,
+                    "assembly {assy} configures node {node}, which is not in its scene"
+                )
+            }
+            Self::InvalidTransform(part) => {
+                write!(f, "part {part} has a transform with invalid values")
+            }
+            Self::InvalidRegion(part) => {
+                write!(
+                    f,
+                    "part {part} has a region drive that does not fit its mesh"
+                )
+            }
+            Self::AssyRegion(assy) => {
+                write!(
+                    f,
+                    "assembly {assy} has a region drive, only parts can have one"
+                )
+            }
+            Self::InvalidShape(part) => write!(f, "part {part} has invalid shape dimensions"),
+            Self::UnknownParameter { part, id } => {
+                write!(f, "part {part} references unknown parameter {id}")
+            }
+            Self::InvalidParameter(id) => write!(
+                f,
+                "parameter {id} needs finite values, min <= default <= max and a positive step"
+            ),
+            Self::InvalidStretch { part, id } => write!(
+                f,
+                "part {part} is stretched by parameter {id}, which needs a min and default above 0"
+            ),
+            Self::ConflictingInclude(part) => {
+                write!(f, "part {part} can not be both an option and a variant")
+            }
+            Self::EmptyChoice(id) => write!(f, "choice {id} has no variants"),
+            Self::UnknownRuleTarget { rule, target } => {
+                write!(f, "rule \"{rule}\" references unknown {target}")
+            }
+            Self::UnknownPresetTarget { preset, target } => {
+                write!(f, "preset {preset} references unknown {target}")
+            }
+            Self::PresetBreaksRule { preset, rule } => {
+                write!(f, "preset {preset} breaks the rule \"{rule}\"")
+            }
+            Self::EmptyCollection(id) => write!(f, "material collection {id} has no materials"),
+            Self::EmptyAssy(name) => write!(f, "assembly {name} has no parts"),
+            Self::InvalidMaterial {
+                material,
+                value,
+                range,
+            } => write!(f, "{value} of material {material} must be {range}"),
+            Self::InvalidColor { collection, color } => {
+                write!(
+                    f,
+                    "material collection {collection} has invalid color {color}"
+                )
+            }
+            Self::NoLibrary(id) => write!(
+                f,
+                "material collection {id} references library materials, but there is no library"
+            ),
+            Self::UnknownLibraryEntry { collection, id } => {
+                write!(
+                    f,
+                    "material collection {collection} references {id}, which is not in the library"
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for DefinitionError {}
+
+impl From<toml::de::Error> for DefinitionError {
+    fn from(e: toml::de::Error) -> Self {
+        Self::Toml(e)
+    }
+}
+impl From<serde_json::Error> for DefinitionError {
+    fn from(e: serde_json::Error) -> Self {
+        Self::Json(e)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MaterialDef {
+    pub name: String,
+    pub rgb: [u8; 3],
+    pub metallic: f32,
+    pub roughness: f32,
+    /// opacity, below 1 for glass and tinted panels
+    #[serde(default = "default_alpha")]
+    pub alpha: f32,
+    /// price delta for every part made of this material
+    #[serde(default)]
+    pub price: Price,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub textures: Option<TexturesDef>,
+}
+
+/// Texture paths of a material, relative to the file declaring it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TexturesDef {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub albedo: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub normal: Option<String>,
+    /// roughness in the green and metallic in the blue channel, as in glTF
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metallic_roughness: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub occlusion: Option<String>,
+    /// how often the textures repeat per uv unit
+    #[serde(default = "default_scale")]
+    pub scale: f32,
+}
+
+fn default_scale() -> f32 {
+    1.0
+}
+
+pub(crate) fn default_alpha() -> f32 {
+    1.0
+}
+
+impl TexturesDef {
+    pub(crate) fn paths_mut(&mut self) -> impl Iterator<Item = &mut String> {
+        [
+            &mut self.albedo,
+            &mut self.normal,
+            &mut self.metallic_roughness,
+            &mut self.occlusion,
+        ]
+        .into_iter()
+        .flatten()
+    }
+}
+
+impl From<&TexturesDef> for Textures {
+    fn from(def: &TexturesDef) -> Self {
+        let map = |path: &Option<String>| path.as_deref().map(TextureMap::new);
+        Textures {
+            albedo: map(&def.albedo),
+            normal: map(&def.normal),
+            metallic_roughness: map(&def.metallic_roughness),
+            occlusion: map(&def.occlusion),
+            scale: def.scale,
+        }
+    }
+}
+
+/// A material defined in place or the id of a library material
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum MaterialRef {
+    Library(String),
+    Inline(MaterialDef),
+}
+
+/// A named group of materials the customer can choose between
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CollectionDef {
+    pub id: String,
+    pub label: String,
+    /// id of a library collection whose materials come first
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub collection: Option<String>,
+    /// path of an MTL file, relative to the definition, whose materials come next
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mtl: Option<String>,
+    #[serde(default)]
+    pub materials: Vec<MaterialRef>,
+    /// an extra entry the customer can give their own color
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub custom: Option<CustomColorDef>,
+}
+
+/// A material with a fixed finish in a color the customer picks, e.g. a powder coating
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CustomColorDef {
+    pub name: String,
+    pub metallic: f32,
+    pub roughness: f32,
+    #[serde(default)]
+    pub price: Price,
+    /// hex colors to pick from, any color if empty
+    #[serde(default)]
+    pub palette: Vec<String>,
+    /// hex color picked by default, the first of the palette or white if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default: Option<String>,
+}
+
+/// An on/off toggle shared by all parts referencing it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OptionDef {
+    pub id: String,
+    pub label: String,
+    #[serde(default = "default_true")]
+    pub default: bool,
+    /// add-on price while the option is on
+    #[serde(default)]
+    pub price: Price,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// Pick exactly one of `variants`, parts reference a variant by name
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChoiceDef {
+    pub id: String,
+    pub label: String,
+    pub variants: Vec<String>,
+    /// index of the variant selected by default
+    #[serde(default)]
+    pub default: usize,
+}
+
+/// A number between `min` and `max` the customer picks with a slider
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ParameterDef {
+    pub id: String,
+    pub label: String,
+    pub min: f32,
+    pub max: f32,
+    /// the value changes in multiples of this, any value if zero
+    #[serde(default)]
+    pub step: f32,
+    /// the value the meshes are modeled at, `min` if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default: Option<f32>,
+    #[serde(default)]
+    pub unit: String,
+    /// add-on price per unit above `min`
+    #[serde(default)]
+    pub price: Price,
+}
+
+/// A parameter moving or stretching a part or assembly
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DriveDef {
+    /// parameter id
+    pub parameter: String,
+    #[serde(flatten)]
+    pub effect: Effect,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VariantDef {
+    pub choice: String,
+    pub name: String,
+}
+
+/// A mesh file or a generated mesh
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum MeshRef {
+    /// path relative to the definition
+    Path(String),
+    Shape(Shape),
+}
+
+/// a string is a path and a table a shape, so a misspelled shape reports the known shapes
+/// instead of not matching an untagged enum
+impl<'de> Deserialize<'de> for MeshRef {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct MeshRefVisitor;
+        impl<'de> de::Visitor<'de> for MeshRefVisitor {
+            type Value = MeshRef;
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                write!(f, "a mesh path or a shape like {{ cylinder = {{ .. }} }}")
+            }
+            fn visit_str<E: de::Error>(self, path: &str) -> Result<MeshRef, E> {
+                Ok(MeshRef::Path(path.into()))
+            }
+            fn visit_map<A: de::MapAccess<'de>>(self, map: A) -> Result<MeshRef, A::Error> {
+                let shape = Shape::deserialize(de::value::MapAccessDeserializer::new(map))?;
+                Ok(MeshRef::Shape(shape))
+            }
+        }
+        deserializer.deserialize_any(MeshRefVisitor)
+    }
+}
+
+impl MeshRef {
+    pub fn path(&self) -> Option<&str> {
+        match self {
+            MeshRef::Path(path) => Some(path),
+            MeshRef::Shape(_) => None,
+        }
+    }
+    pub(crate) fn path_mut(&mut self) -> Option<&mut String> {
+        match self {
+            MeshRef::Path(path) => Some(path),
+            MeshRef::Shape(_) => None,
+        }
+    }
+}
+
+/// A standalone [`Part`] with its own material choice
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PartDef {
+    pub name: String,
+    pub mesh: MeshRef,
+    pub material: String,
+    #[serde(default)]
+    pub optional: bool,
+    #[serde(default)]
+    pub price: Price,
+    #[serde(default)]
+    pub part_number: String,
+    /// placement of the mesh
+    #[serde(default, skip_serializing_if = "Transform::is_identity")]
+    pub transform: Transform,
+}
+
+/// A part of an assembly, materials and options are shared by id
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConfigPartDef {
+    pub name: String,
+    pub mesh: MeshRef,
+    pub material: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub option: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub variant: Option<VariantDef>,
+    #[serde(default)]
+    pub price: Price,
+    #[serde(default)]
+    pub part_number: String,
+    /// placement of the mesh
+    #[serde(default, skip_serializing_if = "Transform::is_identity")]
+    pub transform: Transform,
+    /// draws the mesh once per placement relative to `transform`, counted and priced per copy
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub instances: Vec<Transform>,
+    /// parameters moving or stretching the part
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub drives: Vec<DriveDef>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AssyDef {
+    pub name: String,
+    #[serde(default)]
+    pub parts: Vec<ConfigPartDef>,
+    /// a glTF or GLB file whose meshes are added after `parts`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scene: Option<SceneDef>,
+    /// placement relative to the parent assembly
+    #[serde(default, skip_serializing_if = "Transform::is_identity")]
+    pub transform: Transform,
+    /// shows or hides self with everything below it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub option: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub variant: Option<VariantDef>,
+    /// parameters moving or stretching self with everything below it
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub drives: Vec<DriveDef>,
+    /// sub-assemblies, placed relative to self
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub assys: Vec<AssyDef>,
+}
+
+impl AssyDef {
+    /// self and all its sub-assemblies, parents first
+    pub fn walk(&self) -> Vec<&AssyDef> {
+        let mut assys = vec![self];
+        for child in self.assys.iter() {
+            assys.extend(child.walk());
+        }
+        assys
+    }
+
+    /// builds self and its sub-assemblies
+    fn assy(&self, builder: &mut AssyBuilder) -> crate::Result<Assy> {
+        let (materials, options, choices) = (builder.materials, builder.options, builder.choices);
+        let mut config_parts = Vec::with_capacity(self.parts.len());
+        for p in self.parts.iter() {
+            let material = materials.get(p.material.as_str()).ok_or_else(|| {
+                DefinitionError::UnknownMaterial {
+                    part: p.name.as_str().into(),
+                    id: p.material.as_str().into(),
+                }
+            })?;
+            let include = include_of(
+                options,
+                choices,
+                &p.name,
+                p.option.as_ref(),
+                p.variant.as_ref(),
+            )?;
+            let transform = checked_transform(&p.name, p.transform)?;
+            let instances = p
+                .instances
+                .iter()
+                .map(|instance| checked_transform(&p.name, *instance))
+                .collect::<Result<_, _>>()?;
+            let mesh = builder.mesh(&p.name, &p.mesh)?;
+            let drives = builder.drives(&p.name, &p.drives)?;
+            check_regions(&p.name, &mesh, &drives)?;
+            let body = Body::from(mesh)
+                .with_transform(transform)
+                .with_instances(instances)
+                .with_drives(drives);
+            config_parts.push(
+                ConfigPart::new(p.name.as_str().into(), body, material.clone(), include)
+                    .with_price(p.price)
+                    .with_part_number(p.part_number.as_str()),
+            );
+        }
+        let mut scene_assys = Vec::new();
+        if let Some(scene_def) = self.scene.as_ref() {
+            let scene = &builder.scenes[scene_def.path.as_str()];
+            if let Some(node) = scene_def.unknown_node(scene) {
+                return Err(DefinitionError::UnknownNode {
+                    assy: self.name.as_str().into(),
+                    node: node.into(),
+                }
+                .into());
+            }
+            let (parts, assys) = scene_def.parts(scene, materials, options, choices)?;
+            config_parts.extend(parts);
+            scene_assys = assys;
+        }
+        let children = self
+            .assys
+            .iter()
+            .map(|child| child.assy(builder))
+            .chain(scene_assys.into_iter().map(Ok))
+            .collect::<crate::Result<Box<[_]>>>()?;
+        if config_parts.is_empty() && children.is_empty() {
+            return Err(DefinitionError::EmptyAssy(self.name.as_str().into()).into());
+        }
+        let include = include_of(
+            options,
+            choices,
+            &self.name,
+            self.option.as_ref(),
+            self.variant.as_ref(),
+        )?;
+        if self
+            .drives
+            .iter()
+            .any(|d| matches!(d.effect, Effect::Region(_)))
+        {
+            return Err(DefinitionError::AssyRegion(self.name.as_str().into()).into());
+        }
+        Ok(Assy::nested(config_parts.into(), children)?
+            .with_transform(checked_transform(&self.name, self.transform)?)
+            .with_include(include)
+            .with_drives(builder.drives(&self.name, &self.drives)?))
+    }
+}
+
+/// The loaded files and shared maps `Product::from_def` builds assemblies from
+struct AssyBuilder<'a> {
+    base: &'a str,
+    loaded: RawAssets,
+    /// meshes by resolved path, deserializing takes them out of `loaded`
+    meshes: HashMap<String, TriMesh>,
+    scenes: HashMap<&'a str, Scene>,
+    materials: &'a HashMap<&'a str, SharedMaterial>,
+    options: &'a HashMap<&'a str, Include>,
+    choices: &'a HashMap<&'a str, SharedChoice>,
+    parameters: &'a HashMap<&'a str, SharedParameter>,
+}
+
+impl AssyBuilder<'_> {
+    /// loads or generates the mesh of the part `part`
+    fn mesh(&mut self, part: &str, mesh: &MeshRef) -> crate::Result<TriMesh> {
+        match mesh {
+            MeshRef::Path(path) => {
+                let path = resolve(self.base, path);
+                if !self.meshes.contains_key(&path) {
+                    let mesh = self.loaded.deserialize(&path)?;
+                    self.meshes.insert(path.clone(), mesh);
+                }
+                Ok(self.meshes[&path].clone())
+            }
+            MeshRef::Shape(shape) if shape.is_valid() => Ok(shape.mesh()),
+            MeshRef::Shape(_) => Err(DefinitionError::InvalidShape(part.into()).into()),
+        }
+    }
+
+    /// the drives of the part or assembly `part`
+    fn drives(&self, part: &str, defs: &[DriveDef]) -> Result<Box<[Drive]>, DefinitionError> {
+        defs.iter()
+            .map(|def| {
+                let parameter = self.parameters.get(def.parameter.as_str()).ok_or_else(|| {
+                    DefinitionError::UnknownParameter {
+                        part: part.into(),
+                        id: def.parameter.as_str().into(),
+                    }
+                })?;
+                let finite = match def.effect {
+                    Effect::Translate(per_unit) => per_unit.iter().all(|v| v.is_finite()),
+                    Effect::Stretch(_) => true,
+                    Effect::Region(region) => region.cut.is_finite() && region.per_unit.is_finite(),
+                };
+                if !finite {
+                    return Err(DefinitionError::InvalidTransform(part.into()));
+                }
+                if let Effect::Stretch(_) = def.effect {
+                    let parameter = parameter.borrow();
+                    if parameter.min() <= 0.0 || parameter.default() <= 0.0 {
+                        return Err(DefinitionError::InvalidStretch {
+                            part: part.into(),
+                            id: def.parameter.as_str().into(),
+                        });
+                    }
+                }
+                Ok(Drive::new(parameter, def.effect))
+            })
+            .collect()
+    }
+}
+
+/// An option, material or variant a rule refers to, see [`rules`](super::rules)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ConditionDef {
+    /// option id, or the name of a standalone optional part
+    Option { option: String },
+    /// collection id and material id, library materials have their library id,
+    /// MTL, inline and custom materials their name
+    Material {
+        collection: String,
+        material: String,
+    },
+    /// choice id and variant name
+    Variant { choice: String, variant: String },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RuleDef {
+    pub kind: RuleKind,
+    pub when: ConditionDef,
+    pub then: ConditionDef,
+    /// shown to the user when the rule blocks or corrects a choice
+    pub reason: String,
+}
+
+/// A named configuration, everything not mentioned keeps its default
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PresetDef {
+    pub name: String,
+    /// option id to on or off
+    #[serde(default)]
+    pub options: HashMap<String, bool>,
+    /// material collection id to material id, library materials have their library id,
+    /// MTL, inline and custom materials their name
+    #[serde(default)]
+    pub materials: HashMap<String, String>,
+    /// choice id to variant name
+    #[serde(default)]
+    pub choices: HashMap<String, String>,
+    /// parameter id to value
+    #[serde(default)]
+    pub parameters: HashMap<String, f32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProductDef {
+    pub version: u32,
+    pub name: String,
+    /// shown next to every price
+    #[serde(default)]
+    pub currency: String,
+    /// path of a material library, relative to the definition
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub library: Option<String>,
+    #[serde(default)]
+    pub materials: Vec<CollectionDef>,
+    #[serde(default)]
+    pub options: Vec<OptionDef>,
+    #[serde(default)]
+    pub choices: Vec<ChoiceDef>,
+    #[serde(default)]
+    pub parameters: Vec<ParameterDef>,
+    #[serde(default)]
+    pub parts: Vec<PartDef>,
+    #[serde(default)]
+    pub assys: Vec<AssyDef>,
+    #[serde(default)]
+    pub rules: Vec<RuleDef>,
+    #[serde(default)]
+    pub presets: Vec<PresetDef>,
+}
+
+impl ProductDef {
+    pub fn from_toml(text: &str) -> Result<Self, DefinitionError> {
+        let def: Self = toml::from_str(text)?;
+        def.check_version()
+    }
+
+    pub fn from_json(text: &str) -> Result<Self, DefinitionError> {
+        let def: Self = serde_json::from_str(text)?;
+        def.check_version()
+    }
+
+    /// parses `text` as toml or json depending on the extension of `path`
+    pub fn parse(path: &str, text: &str) -> Result<Self, DefinitionError> {
+        if path.ends_with(".toml") {
+            Self::from_toml(text)
+        } else if path.ends_with(".json") {
+            Self::from_json(text)
+        } else {
+            Err(DefinitionError::UnknownFormat(path.into()))
+        }
+    }
+
+    /// loads a definition from a local file or url
+    pub async fn load(path: &str) -> crate::Result<Self> {
+        let mut loaded = three_d_asset::io::load_async(&[path]).await?;
+        let bytes = loaded.remove(path)?;
+        let text = String::from_utf8_lossy(&bytes);
+        Ok(Self::parse(path, &text)?)
+    }
+
+    pub(crate) fn check_version(self) -> Result<Self, DefinitionError> {
+        if self.version != FORMAT_VERSION {
+            return Err(DefinitionError::Version(self.version));
+        }
+        Ok(self)
+    }
+
+    /// all mesh and scene paths referenced by self, resolved against `base`
+    fn mesh_paths(&self, base: &str) -> Vec<String> {
+        let assys: Vec<&AssyDef> = self.assys.iter().flat_map(|a| a.walk()).collect();
+        let part_meshes = self.parts.iter().filter_map(|p| p.mesh.path());
+        let assy_meshes = assys
+            .iter()
+            .flat_map(|a| a.parts.iter().filter_map(|p| p.mesh.path()));
+        let scenes = assys
+            .iter()
+            .filter_map(|a| a.scene.as_ref().map(|s| s.path.as_str()));
+        let mut paths: Vec<String> = part_meshes
+            .chain(assy_meshes)
+            .chain(scenes)
+            .map(|p| resolve(base, p))
+            .collect();
+        paths.sort();
+        paths.dedup();
+        paths
+    }
+}
+
+/// joins a relative `path` onto the directory `base`
+/// absolute paths and urls are kept as is
+pub(crate) fn resolve(base: &str, path: &str) -> String {
+    if base.is_empty() || path.contains("://") || path.starts_with('/') {
+        path.into()
+    } else {
+        format!("{}/{}", base.trim_end_matches('/'), path)
+    }
+}
+
+/// the directory part of a path or url
+pub(crate) fn parent_dir(path: &str) -> &str {
+    path.rsplit_once('/').map(|(dir, _)| dir).unwrap_or("")
+}
+
+impl TryFrom<&MaterialDef> for Material {
+    type Error = DefinitionError;
+
+    fn try_from(def: &MaterialDef) -> Result<Self, Self::Error> {
+        let material = Material::new(&def.name, def.rgb, def.metallic, def.roughness)
+            .with_alpha(def.alpha)
+            .with_price(def.price);
+        let material = match def.textures.as_ref() {
+            Some(textures) => material.with_textures(textures.into()),
+            None => material,
+        };
+        checked_material(material)
+    }
+}
+
+/// `material` if none of its values is NaN or out of range
+fn checked_material(material: Material) -> Result<Material, DefinitionError> {
+    match material.invalid_values().first() {
+        Some(&(value, range)) => Err(DefinitionError::InvalidMaterial {
+            material: material.name().into(),
+            value,
+            range,
+        }),
+        None => Ok(material),
+    }
+}
+
+impl CollectionDef {
+    /// builds the collection, looking up library references in `library`,
+    /// `mtl` are the materials of the MTL file of self
+    pub(crate) fn collection(
+        &self,
+        library: Option<&MaterialLibrary>,
+        mtl: Vec<Material>,
+    ) -> Result<MaterialCollection, DefinitionError> {
+        let uses_library = self.collection.is_some()
+            || self
+                .materials
+                .iter()
+                .any(|m| matches!(m, MaterialRef::Library(_)));
+        let empty = MaterialLibrary::default();
+        let library = match library {
+            Some(library) => library,
+            None if uses_library => {
+                return Err(DefinitionError::NoLibrary(self.id.as_str().into()))
+            }
+            None => &empty,
+        };
+        let unknown = |id: &str| DefinitionError::UnknownLibraryEntry {
+            collection: self.id.as_str().into(),
+            id: id.into(),
+        };
+
+        let mut materials = Vec::new();
+        if let Some(id) = self.collection.as_ref() {
+            let collection = library.collection(id).ok_or_else(|| unknown(id))?;
+            materials.extend(collection.options().iter().cloned());
+        }
+        materials.extend(mtl);
+        for material in self.materials.iter() {
+            match material {
+                MaterialRef::Library(id) => {
+                    materials.push(library.material(id).ok_or_else(|| unknown(id))?.clone())
+                }
+                MaterialRef::Inline(def) => materials.push(Material::try_from(def)?),
+            }
+        }
+        if materials.is_empty() && self.custom.is_none() {
+            return Err(DefinitionError::EmptyCollection(self.id.as_str().into()));
+        }
+        let collection = MaterialCollection::new(self.label.as_str().into(), materials.into())
+            .with_id(self.id.as_str());
+        match self.custom.as_ref() {
+            Some(custom) => self.with_custom(collection, custom),
+            None => Ok(collection),
+        }
+    }
+
+    /// the ids rules refer to the materials of the collection by, in the order of `collection`,
+    /// library materials by their library id and the others, which have none, by name
+    fn material_ids(&self, library: Option<&MaterialLibrary>, mtl: &[Material]) -> Vec<Box<str>> {
+        let mut ids: Vec<Box<str>> = Vec::new();
+        if let (Some(id), Some(library)) = (self.collection.as_ref(), library) {
+            let library_ids = library.collection_material_ids(id).unwrap_or_default();
+            ids.extend(library_ids.into_iter().map(Box::from));
+        }
+        ids.extend(mtl.iter().map(|m| Box::from(m.name())));
+        for material in self.materials.iter() {
+            ids.push(match material {
+                MaterialRef::Library(id) => id.as_str().into(),
+                MaterialRef::Inline(def) => def.name.as_str().into(),
+            });
+        }
+        if let Some(custom) = self.custom.as_ref() {
+            ids.push(custom.name.as_str().into());
+        }
+        ids
+    }
+
+    fn with_custom(
+        &self,
+        collection: MaterialCollection,
+        custom: &CustomColorDef,
+    ) -> Result<MaterialCollection, DefinitionError> {
+        let color = |hex: &str| {
+            rgb_from_hex(hex).ok_or_else(|| DefinitionError::InvalidColor {
+                collection: self.id.as_str().into(),
+                color: hex.into(),
+            })
+        };
+        let palette = custom
+            .palette
+            .iter()
+            .map(|hex| color(hex))
+            .collect::<Result<Box<[_]>, _>>()?;
+        let rgb = match (custom.default.as_ref(), palette.first()) {
+            (Some(hex), _) => color(hex)?,
+            (None, Some(first)) => *first,
+            (None, None) => [255, 255, 255],
+        };
+        if !palette.is_empty() && !palette.contains(&rgb) {
+            return Err(DefinitionError::InvalidColor {
+                collection: self.id.as_str().into(),
+                color: custom.default.as_deref().unwrap_or_default().into(),
+            });
+        }
+        let material = Material::new(&custom.name, rgb, custom.metallic, custom.roughness)
+            .with_price(custom.price);
+        Ok(collection.with_custom(checked_material(material)?, palette))
+    }
+}
+
+impl Product {
+    /// loads a product from a definition file, mesh paths are relative to the file
+    pub async fn load(path: &str) -> crate::Result<Self> {
+        let def = ProductDef::load(path).await?;
+        Self::from_def(&def, parent_dir(path)).await
+    }
+
+    /// builds a product from `def`, loading meshes and the library relative to `base`
+    pub async fn from_def(def: &ProductDef, base: &str) -> crate::Result<Self> {
+        let library = match def.library.as_ref() {
+            Some(path) => Some(MaterialLibrary::load(&resolve(base, path)).await?),
+            None => None,
+        };
+        let mut mtl_paths: Vec<String> = def
+            .materials
+            .iter()
+            .filter_map(|c| Some(resolve(base, c.mtl.as_ref()?)))
+            .collect();
+        mtl_paths.sort();
+        mtl_paths.dedup();
+        let mtl_files = three_d_asset::io::load_async(&mtl_paths).await?;
+        let mut collections = HashMap::with_capacity(def.materials.len());
+        let mut material_ids = HashMap::with_capacity(def.materials.len());
+        for c in def.materials.iter() {
+            let mtl = match c.mtl.as_ref() {
+                Some(path) => {
+                    let path = resolve(base, path);
+                    let text = String::from_utf8_lossy(mtl_files.get(&path)?);
+                    materials_from_mtl(&path, &text)?
+                }
+                None => Vec::new(),
+            };
+            material_ids.insert(c.id.as_str(), c.material_ids(library.as_ref(), &mtl));
+            collections.insert(c.id.as_str(), c.collection(library.as_ref(), mtl)?);
+        }
+        let materials = collections.values_mut().flat_map(|c| c.options_mut());
+        load_textures(materials, base).await?;
+        let shared: HashMap<&str, SharedMaterial> = collections
+            .iter()
+            .map(|(id, c)| (*id, c.clone().into()))
+            .collect();
+        let options: HashMap<&str, Include> = def
+            .options
+            .iter()
+            .map(|o| {
+                let include = Include::optinal(o.label.as_str(), o.default)
+                    .with_id(o.id.as_str())
+                    .with_price(o.price);
+                (o.id.as_str(), include)
+            })
+            .collect();
+        let choices = def
+            .choices
+            .iter()
+            .map(|c| {
+                if c.variants.is_empty() {
+                    return Err(DefinitionError::EmptyChoice(c.id.as_str().into()));
+                }
+                let variants = c.variants.iter().map(|v| v.as_str().into()).collect();
+                let choice =
+                    Choice::new(c.label.as_str(), variants, c.default).with_id(c.id.as_str());
+                Ok((c.id.as_str(), choice.shared()))
+            })
+            .collect::<Result<HashMap<&str, SharedChoice>, DefinitionError>>()?;
+        let parameters = def
+            .parameters
+            .iter()
+            .map(|p| Ok((p.id.as_str(), p.parameter()?.shared())))
+            .collect::<Result<HashMap<&str, SharedParameter>, DefinitionError>>()?;
+
+        let paths = def.mesh_paths(base);
+        let loaded = three_d_asset::io::load_async(&paths).await?;
+        let mut scenes: HashMap<&str, Scene> = HashMap::new();
+        let assy_defs = def.assys.iter().flat_map(|a| a.walk());
+        for scene_def in assy_defs.filter_map(|a| a.scene.as_ref()) {
+            if scenes.contains_key(scene_def.path.as_str()) {
+                continue;
+            }
+            let path = resolve(base, &scene_def.path);
+            // obj files are split by group here, three-d-asset only splits them by object
+            let scene = if path.ends_with(".obj") {
+                scene_from_obj(&path, &loaded)?
+            } else {
+                loaded.deserialize(&path)?
+            };
+            scenes.insert(scene_def.path.as_str(), scene);
+        }
+        let mut builder = AssyBuilder {
+            base,
+            loaded,
+            meshes: HashMap::new(),
+            scenes,
+            materials: &shared,
+            options: &options,
+            choices: &choices,
+            parameters: &parameters,
+        };
+
+        let mut parts = Vec::with_capacity(def.parts.len());
+        for p in def.parts.iter() {
+            let material = collections.get(p.material.as_str()).ok_or_else(|| {
+                DefinitionError::UnknownMaterial {
+                    part: p.name.as_str().into(),
+                    id: p.material.as_str().into(),
+                }
+            })?;
+            let shape = builder.mesh(&p.name, &p.mesh)?;
+            parts.push(
+                Part::new(p.name.as_str().into(), shape, material.clone(), p.optional)?
+                    .with_price(p.price)
+                    .with_part_number(p.part_number.as_str())
+                    .with_transform(checked_transform(&p.name, p.transform)?),
+            );
+        }
+
+        let assys = def
+            .assys
+            .iter()
+            .map(|a| a.assy(&mut builder))
+            .collect::<crate::Result<Box<[_]>>>()?;
+
+        let mut product = Self::new(&def.name, parts.into(), assys);
+        product.currency = def.currency.as_str().into();
+        product.rules = def
+            .rules
+            .iter()
+            .map(|rule| rule.rule(def, &material_ids, &builder))
+            .collect::<Result<_, _>>()?;
+
+        let defaults = product.configuration();
+        let mut presets = Vec::with_capacity(def.presets.len());
+        for preset in def.presets.iter() {
+            product.configure_preset(def, preset, &material_ids, &shared, &builder)?;
+            if let Some(rule) = product.broken_rule() {
+                return Err(DefinitionError::PresetBreaksRule {
+                    preset: preset.name.as_str().into(),
+                    rule: rule.reason.clone(),
+                }
+                .into());
+            }
+            presets.push(Preset::new(&preset.name, product.configuration()));
+            product.configure(&defaults)?;
+        }
+        product.set_presets(presets.into());
+        Ok(product)
+    }
+
+    /// sets everything `preset` mentions, the shared maps are the ones built by `from_def`
+    fn configure_preset(
+        &mut self,
+        def: &ProductDef,
+        preset: &PresetDef,
+        material_ids: &HashMap<&str, Vec<Box<str>>>,
+        materials: &HashMap<&str, SharedMaterial>,
+        builder: &AssyBuilder,
+    ) -> Result<(), DefinitionError> {
+        let (options, choices) = (builder.options, builder.choices);
+        let unknown = |target: String| DefinitionError::UnknownPresetTarget {
+            preset: preset.name.as_str().into(),
+            target: target.into(),
+        };
+        for (id, value) in preset.options.iter() {
+            let include = options
+                .get(id.as_str())
+                .ok_or_else(|| unknown(format!("option {id}")))?;
+            include.set(*value);
+        }
+        for (id, material) in preset.materials.iter() {
+            let ids = material_ids
+                .get(id.as_str())
+                .ok_or_else(|| unknown(format!("material collection {id}")))?;
+            let index = ids
+                .iter()
+                .position(|m| **m == **material)
+                .ok_or_else(|| unknown(format!("material {material} of {id}")))?;
+            if let Some(material) = materials.get(id.as_str()) {
+                material.borrow_mut().select(index);
+            }
+            for (part, part_def) in self.parts.iter_mut().zip(def.parts.iter()) {
+                if part_def.material == *id {
+                    part.materials_mut().select(index);
+                }
+            }
+        }
+        for (id, name) in preset.choices.iter() {
+            let choice = choices
+                .get(id.as_str())
+                .ok_or_else(|| unknown(format!("choice {id}")))?;
+            let index = choice
+                .borrow()
+                .variants()
+                .iter()
+                .position(|v| **v == **name)
+                .ok_or_else(|| unknown(format!("variant {name} of {id}")))?;
+            choice.borrow_mut().select(index);
+        }
+        for (id, value) in preset.parameters.iter() {
+            let parameter = builder
+                .parameters
+                .get(id.as_str())
+                .ok_or_else(|| unknown(format!("parameter {id}")))?;
+            if !parameter.borrow().allows(*value) {
+                return Err(unknown(format!("value {value} of {id}")));
+            }
+            parameter.borrow_mut().set(*value);
+        }
+        Ok(())
+    }
+}
+
+impl ParameterDef {
+    fn parameter(&self) -> Result<Parameter, DefinitionError> {
+        let default = self.default.unwrap_or(self.min);
+        let valid = [self.min, self.max, self.step, default]
+            .iter()
+            .all(|v| v.is_finite())
+            && self.min <= default
+            && default <= self.max
+            && self.step >= 0.0;
+        let invalid = || DefinitionError::InvalidParameter(self.id.as_str().into());
+        if !valid {
+            return Err(invalid());
+        }
+        let parameter = Parameter::new(self.label.as_str(), self.min, self.max, default)
+            .with_step(self.step)
+            .with_unit(self.unit.as_str())
+            .with_price(self.price);
+        // the default has to be a step of the slider
+        if !parameter.allows(default) {
+            return Err(invalid());
+        }
+        Ok(parameter)
+    }
+}
+
+impl RuleDef {
+    /// resolves the ids of the conditions, the maps are the ones built by `from_def`
+    fn rule(
+        &self,
+        def: &ProductDef,
+        material_ids: &HashMap<&str, Vec<Box<str>>>,
+        builder: &AssyBuilder,
+    ) -> Result<Rule, DefinitionError> {
+        let condition = |condition: &ConditionDef| {
+            condition
+                .condition(def, material_ids, builder)
+                .map_err(|target| DefinitionError::UnknownRuleTarget {
+                    rule: self.reason.as_str().into(),
+                    target: target.into(),
+                })
+        };
+        Ok(Rule {
+            kind: self.kind,
+            when: condition(&self.when)?,
+            then: condition(&self.then)?,
+            reason: self.reason.as_str().into(),
+        })
+    }
+}
+
+impl ConditionDef {
+    /// the condition with materials and variants resolved to indices,
+    /// or a description of the unknown target
+    fn condition(
+        &self,
+        def: &ProductDef,
+        material_ids: &HashMap<&str, Vec<Box<str>>>,
+        builder: &AssyBuilder,
+    ) -> Result<Condition, String> {
+        match self {
+            ConditionDef::Option { option } => {
+                let part = def.parts.iter().any(|p| p.optional && p.name == *option);
+                if !part && !builder.options.contains_key(option.as_str()) {
+                    return Err(format!("option {option}"));
+                }
+                Ok(Condition::option(option))
+            }
+            ConditionDef::Material {
+                collection,
+                material,
+            } => {
+                let ids = material_ids
+                    .get(collection.as_str())
+                    .ok_or_else(|| format!("material collection {collection}"))?;
+                let index = ids
+                    .iter()
+                    .position(|id| **id == **material)
+                    .ok_or_else(|| format!("material {material} of {collection}"))?;
+                Ok(Condition::material(collection, index))
+            }
+            ConditionDef::Variant { choice, variant } => {
+                let shared = builder
+                    .choices
+                    .get(choice.as_str())
+                    .ok_or_else(|| format!("choice {choice}"))?;
+                let index = shared
+                    .borrow()
+                    .variants()
+                    .iter()
+                    .position(|v| **v == **variant)
+                    .ok_or_else(|| format!("variant {variant} of {choice}"))?;
+                Ok(Condition::variant(choice, index))
+            }
+        }
+    }
+}
+
+fn checked_transform(part: &str, transform: Transform) -> Result<Transform, DefinitionError> {
+    if !transform.is_finite() || transform.scale.iter().any(|s| *s <= 0.0) {
+        return Err(DefinitionError::InvalidTransform(part.into()));
+    }
+    Ok(transform)
+}
+
+/// every region of `drives` has to fit `mesh`, see [`Region::fits`](super::parameter::Region::fits)
+fn check_regions(part: &str, mesh: &TriMesh, drives: &[Drive]) -> Result<(), DefinitionError> {
+    let bb = mesh.compute_aabb();
+    for drive in drives.iter() {
+        if let Effect::Region(region) = drive.effect() {
+            if !region.fits(&bb, &drive.parameter().borrow()) {
+                return Err(DefinitionError::InvalidRegion(part.into()));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// how a part with `option` or `variant` is included, the maps are the ones of `from_def`
+pub(crate) fn include_of(
+    options: &HashMap<&str, Include>,
+    choices: &HashMap<&str, SharedChoice>,
+    part: &str,
+    option: Option<&String>,
+    variant: Option<&VariantDef>,
+) -> Result<Include, DefinitionError> {
+    match (option, variant) {
+        (None, None) => Ok(Include::MustHave),
+        (Some(id), None) => {
+            options
+                .get(id.as_str())
+                .cloned()
+                .ok_or_else(|| DefinitionError::UnknownOption {
+                    part: part.into(),
+                    id: id.as_str().into(),
+                })
+        }
+        (None, Some(variant)) => variant_include(choices, part, variant),
+        (Some(_), Some(_)) => Err(DefinitionError::ConflictingInclude(part.into())),
+    }
+}
+
+fn variant_include(
+    choices: &HashMap<&str, SharedChoice>,
+    part: &str,
+    variant: &VariantDef,
+) -> Result<Include, DefinitionError> {
+    let choice =
+        choices
+            .get(variant.choice.as_str())
+            .ok_or_else(|| DefinitionError::UnknownChoice {
+                part: part.into(),
+                id: variant.choice.as_str().into(),
+            })?;
+    let index = choice
+        .borrow()
+        .variants()
+        .iter()
+        .position(|v| **v == *variant.name)
+        .ok_or_else(|| DefinitionError::UnknownVariant {
+            part: part.into(),
+            choice: variant.choice.as_str().into(),
+            name: variant.name.as_str().into(),
+        })?;
+    Ok(Include::variant(choice, index))
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+
+    /// a stool made of generated meshes, so nothing has to be loaded
+    const STOOL: &str = r#"
+version = 1
+name = "Stool"
+
+[[materials]]
+id = "metals"
+label = "Metals"
+materials = [
+    { name = "Steel", rgb = [130, 130, 130], metallic = 0.8, roughness = 0.3 },
+    { name = "Gold", rgb = [212, 175, 55], metallic = 0.9, roughness = 0.2 },
+]
+
+[[materials]]
+id = "fabrics"
+label = "Fabrics"
+materials = [
+    { name = "Pink", rgb = [255, 138, 201], metallic = 0.3, roughness = 0.9 },
+    { name = "Dark", rgb = [20, 39, 46], metallic = 0.3, roughness = 0.9 },
+]
+
+[[options]]
+id = "back"
+label = "Backrest"
+default = false
+
+[[choices]]
+id = "legs"
+label = "Legs"
+variants = ["Four", "Three"]
+
+[[assys]]
+name = "Stool"
+
+[[assys.parts]]
+name = "Seat"
+mesh = { cylinder = { radius = 200.0, height = 40.0 } }
+material = "fabrics"
+
+[[assys.parts]]
+name = "Back"
+mesh = { plane = { size = [400.0, 300.0] } }
+material = "metals"
+option = "back"
+
+[[assys.parts]]
+name = "Four Legs"
+mesh = { cylinder = { radius = 15.0, height = 450.0 } }
+material = "metals"
+variant = { choice = "legs", name = "Four" }
+
+[[assys.parts]]
+name = "Three Legs"
+mesh = { cylinder = { radius = 15.0, height = 450.0 } }
+material = "metals"
+variant = { choice = "legs", name = "Three" }
+"#;
+
+    async fn stool(rules: &str) -> crate::Result<Product> {
+        let text = format!("{STOOL}\n{rules}");
+        let def = ProductDef::from_toml(&text)?;
+        Product::from_def(&def, "").await
+    }
+
+    fn unknown_target(result: crate::Result<Product>) -> Box<str> {
+        match result {
+            Err(crate::Error::Definition(DefinitionError::UnknownRuleTarget {
+                target, ..
+            })) => target,
+            Err(e) => panic!("unexpected error {e}"),
+            Ok(_) => panic!("unknown rule target was accepted"),
+        }
+    }
+
+    #[tokio::test]
+    async fn rules_resolve_ids() {
+        let rules = r#"
+[[rules]]
+kind = "excludes"
+when = { collection = "metals", material = "Gold" }
+then = { collection = "fabrics", material = "Pink" }
+reason = "no gold with pink"
+
+[[rules]]
+kind = "requires"
+when = { choice = "legs", variant = "Three" }
+then = { option = "back" }
+reason = "three legs need a backrest"
+"#;
+        let product = stool(rules).await.unwrap();
+        let gold = Condition::material("metals", 1);
+        let three_legs = Condition::variant("legs", 1);
+        assert_eq!(product.rules()[0].when, gold);
+        assert_eq!(product.rules()[0].then, Condition::material("fabrics", 0));
+        assert_eq!(product.rules()[1].when, three_legs);
+        assert_eq!(product.rules()[1].then, Condition::option("back"));
+
+        let blocked = product.blocked();
+        assert_eq!(blocked.reason(&gold, true), Some("no gold with pink"));
+        assert_eq!(
+            blocked.reason(&three_legs, true),
+            Some("three legs need a backrest")
+        );
+        assert_eq!(
+            blocked.reason(&Condition::material("metals", 0), true),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn rules_reject_unknown_ids() {
+        let rule = |when: &str| {
+            format!(
+                "[[rules]]\nkind = \"excludes\"\nwhen = {when}\n\
+                 then = {{ option = \"back\" }}\nreason = \"test\""
+            )
+        };
+        let cases = [
+            // labels are not ids
+            (r#"{ option = "Backrest" }"#, "option Backrest"),
+            (
+                r#"{ collection = "Metals", material = "Gold" }"#,
+                "material collection Metals",
+            ),
+            (
+                r#"{ collection = "metals", material = "Brass" }"#,
+                "material Brass of metals",
+            ),
+            (r#"{ choice = "Legs", variant = "Four" }"#, "choice Legs"),
+            (
+                r#"{ choice = "legs", variant = "Five" }"#,
+                "variant Five of legs",
+            ),
+        ];
+        for (when, target) in cases {
+            assert_eq!(&*unknown_target(stool(&rule(when)).await), target);
+        }
+    }
+
+    const NO_GOLD_WITH_PINK: &str = r#"
+[[rules]]
+kind = "excludes"
+when = { collection = "metals", material = "Gold" }
+then = { collection = "fabrics", material = "Pink" }
+reason = "no gold with pink"
+"#;
+
+    #[tokio::test]
+    async fn presets_reference_ids() {
+        let preset = r#"
+[[presets]]
+name = "Fancy"
+options = { back = true }
+materials = { metals = "Gold", fabrics = "Dark" }
+choices = { legs = "Three" }
+"#;
+        let product = stool(&format!("{NO_GOLD_WITH_PINK}\n{preset}"))
+            .await
+            .unwrap();
+        let config = product.presets()[0].configuration();
+        assert_eq!(config.assys[0].includes, vec![true]);
+        assert_eq!(config.assys[0].materials, vec![1, 1]);
+        assert_eq!(config.assys[0].choices, vec![1]);
+
+        let by_label = r#"
+[[presets]]
+name = "Fancy"
+materials = { Metals = "Gold" }
+"#;
+        match stool(by_label).await {
+            Err(crate::Error::Definition(DefinitionError::UnknownPresetTarget {
+                target, ..
+            })) => {
+                assert_eq!(&*target, "material collection Metals")
+            }
+            _ => panic!("a collection label was accepted as id"),
+        }
+    }
+
+    #[tokio::test]
+    async fn presets_follow_the_rules() {
+        let preset = r#"
+[[presets]]
+name = "Clash"
+materials = { metals = "Gold" }
+"#;
+        match stool(&format!("{NO_GOLD_WITH_PINK}\n{preset}")).await {
+            Err(crate::Error::Definition(DefinitionError::PresetBreaksRule { preset, rule })) => {
+                assert_eq!((&*preset, &*rule), ("Clash", "no gold with pink"))
+            }
+            _ => panic!("a preset breaking a rule was accepted"),
+        }
+    }
+
+    #[tokio::test]
+    async fn parts_share_mesh_files() {
+        let dir = std::env::temp_dir().join("cfg3demo-shared-mesh");
+        std::fs::create_dir_all(&dir).unwrap();
+        let obj = "o Leg\nv 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n";
+        std::fs::write(dir.join("leg.obj"), obj).unwrap();
+        let text = r#"
+version = 1
+name = "Table"
+
+[[materials]]
+id = "metals"
+label = "Metals"
+materials = [{ name = "Steel", rgb = [130, 130, 130], metallic = 0.8, roughness = 0.3 }]
+
+[[parts]]
+name = "Leg"
+mesh = "leg.obj"
+material = "metals"
+
+[[assys]]
+name = "Frame"
+
+[[assys.parts]]
+name = "Left Leg"
+mesh = "leg.obj"
+material = "metals"
+
+[[assys.parts]]
+name = "Right Leg"
+mesh = "leg.obj"
+material = "metals"
+transform = { translation = [1.0, 0.0, 0.0] }
+"#;
+        let def = ProductDef::from_toml(text).unwrap();
+        let product = Product::from_def(&def, dir.to_str().unwrap())
+            .await
+            .unwrap();
+        let parts = product.assys()[0].parts();
+        assert_eq!(parts.len(), 2);
+        assert_eq!(parts[0].body().shape().vertex_count(), 3);
+        assert_eq!(parts[1].body().shape().vertex_count(), 3);
+        assert_eq!(product.parts()[0].shape().vertex_count(), 3);
+    }
+
+    #[tokio::test]
+    async fn regions_have_to_fit_their_mesh() {
+        let top = |cut: f32| {
+            format!(
+                r#"
+version = 1
+name = "Table"
+
+[[materials]]
+id = "wood"
+label = "Wood"
+materials = [{{ name = "Oak", rgb = [190, 140, 90], metallic = 0.0, roughness = 0.8 }}]
+
+[[parameters]]
+id = "width"
+label = "Width"
+min = 300.0
+max = 600.0
+default = 400.0
+
+[[assys]]
+name = "Table"
+
+[[assys.parts]]
+name = "Top"
+mesh = {{ plane = {{ size = [400.0, 300.0] }} }}
+material = "wood"
+drives = [{{ parameter = "width", region = {{ axis = "x", cut = {cut}, per_unit = 0.5 }} }}]
+"#
+            )
+        };
+        let load = |cut| async move {
+            let def = ProductDef::from_toml(&top(cut)).unwrap();
+            Product::from_def(&def, "").await
+        };
+        assert!(load(100.0).await.is_ok());
+        // shrinking by 50 at the lowest width would move the edge past the cut
+        for cut in [160.0, -160.0, 200.0, 300.0] {
+            match load(cut).await {
+                Err(crate::Error::Definition(DefinitionError::InvalidRegion(part))) => {
+                    assert_eq!(&*part, "Top")
+                }
+                _ => panic!("region with cut {cut} was accepted"),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn assemblies_have_no_regions() {
+        let text = r#"
+version = 1
+name = "Table"
+
+[[materials]]
+id = "wood"
+label = "Wood"
+materials = [{ name = "Oak", rgb = [190, 140, 90], metallic = 0.0, roughness = 0.8 }]
+
+[[parameters]]
+id = "width"
+label = "Width"
+min = 300.0
+max = 600.0
+default = 400.0
+
+[[assys]]
+name = "Table"
+drives = [{ parameter = "width", region = { axis = "x", cut = 100.0, per_unit = 0.5 } }]
+
+[[assys.parts]]
+name = "Top"
+mesh = { plane = { size = [400.0, 300.0] } }
+material = "wood"
+"#;
+        let def = ProductDef::from_toml(text).unwrap();
+        match Product::from_def(&def, "").await {
+            Err(crate::Error::Definition(DefinitionError::AssyRegion(assy))) => {
+                assert_eq!(&*assy, "Table")
+            }
+            _ => panic!("region drive of an assembly was accepted"),
+        }
+    }
+
+    #[tokio::test]
+    async fn stretched_parameters_stay_positive() {
+        let table = |min: f32| {
+            format!(
+                r#"
+version = 1
+name = "Table"
+
+[[materials]]
+id = "wood"
+label = "Wood"
+materials = [{{ name = "Oak", rgb = [190, 140, 90], metallic = 0.0, roughness = 0.8 }}]
+
+[[parameters]]
+id = "width"
+label = "Width"
+min = {min}
+max = 600.0
+default = 400.0
+
+[[assys]]
+name = "Table"
+
+[[assys.parts]]
+name = "Top"
+mesh = {{ plane = {{ size = [400.0, 300.0] }} }}
+material = "wood"
+drives = [{{ parameter = "width", stretch = "x" }}]
+"#
+            )
+        };
+        let load = |min| async move {
+            let def = ProductDef::from_toml(&table(min)).unwrap();
+            Product::from_def(&def, "").await
+        };
+        assert!(load(300.0).await.is_ok());
+        for min in [0.0, -100.0] {
+            match load(min).await {
+                Err(crate::Error::Definition(DefinitionError::InvalidStretch { part, id })) => {
+                    assert_eq!((&*part, &*id), ("Top", "width"))
+                }
+                _ => panic!("stretch with min {min} was accepted"),
+            }
+        }
+    }
+
+    #[test]
+    fn mesh_refs() {
+        let mesh = |text: &str| {
+            toml::from_str::<PartDef>(&format!("{text}\nname = \"Leg\"\nmaterial = \"metals\""))
+                .map(|p| p.mesh)
+        };
+        assert_eq!(
+            mesh(r#"mesh = "leg.obj""#).unwrap(),
+            MeshRef::Path("leg.obj".into())
+        );
+        let cylinder = mesh("mesh = { cylinder = { radius = 20.0, height = 700.0 } }");
+        assert!(matches!(
+            cylinder.unwrap(),
+            MeshRef::Shape(Shape::Cylinder { segments: 32, .. })
+        ));
+
+        let misspelled = mesh("mesh = { cilinder = { radius = 20.0, height = 700.0 } }")
+            .unwrap_err()
+            .to_string();
+        assert!(
+            misspelled.contains("unknown variant `cilinder`"),
+            "{misspelled}"
+        );
+        assert!(misspelled.contains("`cylinder`"), "{misspelled}");
+        let missing = mesh("mesh = { cylinder = { radius = 20.0 } }")
+            .unwrap_err()
+            .to_string();
+        assert!(missing.contains("missing field `height`"), "{missing}");
+        let number = mesh("mesh = 3").unwrap_err().to_string();
+        assert!(number.contains("a mesh path or a shape"), "{number}");
+    }
+
+    #[test]
+    fn texture_scale_is_checked() {
+        let def = |scale| MaterialDef {
+            name: "Oak".into(),
+            rgb: [190, 140, 90],
+            metallic: 0.0,
+            roughness: 0.8,
+            alpha: 1.0,
+            price: Price::ZERO,
+            textures: Some(TexturesDef {
+                albedo: Some("oak.png".into()),
+                normal: None,
+                metallic_roughness: None,
+                occlusion: None,
+                scale,
+            }),
+        };
+        assert!(Material::try_from(&def(2.5)).is_ok());
+        for scale in [0.0, -1.0, f32::NAN, f32::INFINITY] {
+            assert!(matches!(
+                Material::try_from(&def(scale)),
+                Err(DefinitionError::InvalidMaterial {
+                    value: "texture scale",
+                    ..
+                })
+            ));
+        }
+    }
+
+    #[test]
+    fn custom_materials_are_checked() {
+        let coats = |metallic| CollectionDef {
+            id: "coats".into(),
+            label: "Coats".into(),
+            collection: None,
+            mtl: None,
+            materials: Vec::new(),
+            custom: Some(CustomColorDef {
+                name: "Powder Coat".into(),
+                metallic,
+                roughness: 0.6,
+                price: Price::ZERO,
+                palette: Vec::new(),
+                default: None,
+            }),
+        };
+        assert!(coats(0.1).collection(None, Vec::new()).is_ok());
+        for metallic in [f32::NAN, 2.0] {
+            assert!(matches!(
+                coats(metallic).collection(None, Vec::new()),
+                Err(DefinitionError::InvalidMaterial {
+                    value: "metallic",
+                    ..
+                })
+            ));
+        }
+    }
+
+    #[test]
+    fn transforms_are_checked() {
+        let scaled = |scale| Transform {
+            scale,
+            ..Transform::IDENTITY
+        };
+        assert!(checked_transform("Leg", scaled([0.5, 2.0, 1.0])).is_ok());
+        for scale in [[0.0, 1.0, 1.0], [1.0, -1.0, 1.0], [1.0, 1.0, f32::NAN]] {
+            assert!(matches!(
+                checked_transform("Leg", scaled(scale)),
+                Err(DefinitionError::InvalidTransform(_))
+            ));
+        }
+        let moved = Transform {
+            translation: [f32::INFINITY, 0.0, 0.0],
+            ..Transform::IDENTITY
+        };
+        assert!(checked_transform("Leg", moved).is_err());
+    }
+
+    #[test]
+    fn material_values_are_checked() {
+        let def = |metallic, roughness, alpha| MaterialDef {
+            name: "Glass".into(),
+            rgb: [200, 220, 230],
+            metallic,
+            roughness,
+            alpha,
+            price: Price::ZERO,
+            textures: None,
+        };
+        assert!(Material::try_from(&def(0.0, 0.1, 0.0)).is_ok());
+        assert!(Material::try_from(&def(1.0, 1.0, 1.0)).is_ok());
+        let cases = [
+            (def(f32::NAN, 0.1, 1.0), "metallic"),
+            (def(1.2, 0.1, 1.0), "metallic"),
+            (def(0.0, -0.1, 1.0), "roughness"),
+            (def(0.0, f32::NAN, 1.0), "roughness"),
+            (def(0.0, 0.1, 1.5), "alpha"),
+            (def(0.0, 0.1, f32::NAN), "alpha"),
+        ];
+        for (def, expected) in cases {
+            match Material::try_from(&def) {
+                Err(DefinitionError::InvalidMaterial { value, .. }) => assert_eq!(value, expected),
+                _ => panic!("invalid {expected} was accepted"),
+            }
+        }
+    }
+}
@@ -0,0 +1,417 @@
+//! Assemblies imported from a glTF, GLB or OBJ scene.
+//!
+//! Every mesh of the scene becomes a [`ConfigPart`] named after its node and every node with
+//! children a nested [`Assy`], both placed by the transform of the node. glTF material names
+//! are mapped onto the material collections of the definition, unmapped meshes keep the
+//! material of the scene. Nodes can be made optional or variants by name, everything below
+//! such a node inherits it. A single OBJ is split by its objects and groups, see
+//! [`super::obj`], its `usemtl` slots are mapped like glTF materials and default to the
+//! values of its MTL file.
+//!
+//! ```toml
+//! [[assys]]
+//! name = "Chair"
+//!
+//! [assys.scene]
+//! path = "chair.glb"
+//! materials = { Steel = "metals", Cloth = "fabrics" }
+//! fallback = "plastic"
+//!
+//! [assys.scene.nodes]
+//! Arms = { option = "arms", price = 45.0 }
+//! Seat = { part_number = "CH-300", material = "fabrics" }
+//! ```
+use std::collections::{HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+use three_d_asset::{Geometry, Mat4, Node, Scene, TriMesh};
+
+use super::{
+    assembly::{Assy, ConfigPart, Include, SharedChoice},
+    definition::{include_of, DefinitionError, VariantDef},
+    material::{Material, SharedMaterial},
+    price::Price,
+    transform::Transform,
+    Body,
+};
+
+/// A glTF, GLB or OBJ file whose meshes become the parts of an assembly
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SceneDef {
+    /// path of the file, relative to the definition
+    pub path: String,
+    /// glTF material name to material collection id
+    #[serde(default)]
+    pub materials: HashMap<String, String>,
+    /// material collection id for meshes whose glTF material is not mapped,
+    /// without it they keep the material of the scene
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fallback: Option<String>,
+    /// node name to the settings of the parts at and below that node
+    #[serde(default)]
+    pub nodes: HashMap<String, NodeDef>,
+}
+
+/// How the parts of a node are included, billed and which material they use
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct NodeDef {
+    /// material collection id, overrides the mapping of the scene material
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub material: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub option: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub variant: Option<VariantDef>,
+    #[serde(default)]
+    pub price: Price,
+    #[serde(default)]
+    pub part_number: String,
+}
+
+impl SceneDef {
+    /// a part per mesh at the top of `scene` and an assembly per node with children,
+    /// the maps are the ones built by `Product::from_def`
+    pub(crate) fn parts(
+        &self,
+        scene: &Scene,
+        materials: &HashMap<&str, SharedMaterial>,
+        options: &HashMap<&str, Include>,
+        choices: &HashMap<&str, SharedChoice>,
+    ) -> crate::Result<(Vec<ConfigPart>, Vec<Assy>)> {
+        let mut builder = NodeBuilder {
+            def: self,
+            scene,
+            materials,
+            options,
+            choices,
+            defaults: HashMap::new(),
+            node_names: node_names(scene),
+            part_names: HashSet::new(),
+        };
+        let mut parts = Vec::new();
+        let mut assys = Vec::new();
+        for node in scene.children.iter() {
+            builder.add(node, Mat4::from_scale(1.0), &[], &mut parts, &mut assys)?;
+        }
+        Ok((parts, assys))
+    }
+
+    /// the first node name used by self that `scene` does not have
+    pub(crate) fn unknown_node<'a>(&'a self, scene: &Scene) -> Option<&'a str> {
+        let known = node_names(scene);
+        self.nodes
+            .keys()
+            .map(|n| n.as_str())
+            .find(|n| !known.contains(n))
+    }
+}
+
+/// the names of all nodes of `scene`
+fn node_names(scene: &Scene) -> HashSet<&str> {
+    let mut names = HashSet::new();
+    let mut stack: Vec<&Node> = scene.children.iter().collect();
+    while let Some(node) = stack.pop() {
+        names.insert(node.name.as_str());
+        stack.extend(node.children.iter());
+    }
+    names
+}
+
+/// Turns the nodes of a scene into parts and assemblies
+struct NodeBuilder<'a> {
+    def: &'a SceneDef,
+    scene: &'a Scene,
+    materials: &'a HashMap<&'a str, SharedMaterial>,
+    options: &'a HashMap<&'a str, Include>,
+    choices: &'a HashMap<&'a str, SharedChoice>,
+    /// scene materials used as is, shared by all meshes using them
+    defaults: HashMap<usize, SharedMaterial>,
+    node_names: HashSet<&'a str>,
+    part_names: HashSet<String>,
+}
+
+impl<'a> NodeBuilder<'a> {
+    /// pushes the part of `node` if it has no children, or the assembly of it and its children
+    /// otherwise, placed by the node transform. `bake` is the part of the transforms above that
+    /// does not fit a [`Transform`], it is applied to the vertices instead.
+    fn add(
+        &mut self,
+        node: &'a Node,
+        bake: Mat4,
+        names: &[&'a str],
+        parts: &mut Vec<ConfigPart>,
+        assys: &mut Vec<Assy>,
+    ) -> crate::Result<()> {
+        let matrix = bake * node.transformation;
+        let (transform, bake) = match Transform::from_matrix(matrix) {
+            Some(transform) => (transform, Mat4::from_scale(1.0)),
+            None => (Transform::IDENTITY, matrix),
+        };
+        let mut node_names = Vec::with_capacity(names.len() + 1);
+        if !node.name.is_empty() {
+            node_names.push(node.name.as_str());
+        }
+        node_names.extend_from_slice(names);
+
+        if node.children.is_empty() {
+            parts.extend(self.part(node, &node_names, transform, bake)?);
+            return Ok(());
+        }
+        let mut node_parts = Vec::new();
+        node_parts.extend(self.part(node, &node_names, Transform::IDENTITY, bake)?);
+        let mut children = Vec::new();
+        for child in node.children.iter() {
+            self.add(child, bake, &node_names, &mut node_parts, &mut children)?;
+        }
+        // nodes without any mesh below them are left out
+        if !node_parts.is_empty() || !children.is_empty() {
+            assys.push(Assy::nested(node_parts.into(), children.into())?.with_transform(transform));
+        }
+        Ok(())
+    }
+
+    /// the part of the mesh of `node`, if it has one, `names` are the names of the node and
+    /// its parents, innermost first
+    fn part(
+        &mut self,
+        node: &Node,
+        names: &[&str],
+        transform: Transform,
+        bake: Mat4,
+    ) -> crate::Result<Option<ConfigPart>> {
+        let Some(Geometry::Triangles(mesh)) = node.geometry.as_ref() else {
+            return Ok(None);
+        };
+        let mut mesh = mesh.clone();
+        if bake != Mat4::from_scale(1.0) {
+            mesh.transform(&bake)?;
+        }
+        let name = self.part_name(names.first().copied().unwrap_or("Mesh"));
+
+        let default = NodeDef::default();
+        let node_def = names
+            .iter()
+            .find_map(|n| self.def.nodes.get(*n))
+            .unwrap_or(&default);
+
+        let scene_material = node
+            .material_index
+            .and_then(|i| Some((i, self.scene.materials.get(i)?)));
+        let gltf_material = scene_material
+            .map(|(_, m)| m.name.as_str())
+            .unwrap_or_default();
+        let id = node_def
+            .material
+            .as_ref()
+            .or_else(|| self.def.materials.get(gltf_material))
+            .or(self.def.fallback.as_ref());
+        let material = match (id, scene_material) {
+            (Some(id), _) => self.materials.get(id.as_str()).cloned().ok_or_else(|| {
+                DefinitionError::UnknownMaterial {
+                    part: name.as_str().into(),
+                    id: id.as_str().into(),
+                }
+            })?,
+            (None, Some((index, pbr))) => self
+                .defaults
+                .entry(index)
+                .or_insert_with(|| Material::from(pbr).into())
+                .clone(),
+            (None, None) => return Err(DefinitionError::NoMaterial(name.into()).into()),
+        };
+        let include = include_of(
+            self.options,
+            self.choices,
+            &name,
+            node_def.option.as_ref(),
+            node_def.variant.as_ref(),
+        )?;
+        let body = Body::from(mesh).with_transform(transform);
+        Ok(Some(
+            ConfigPart::new(name.into(), body, material, include)
+                .with_price(node_def.price)
+                .with_part_number(node_def.part_number.as_str()),
+        ))
+    }
+
+    /// `node_name`, or with a " (n)" suffix if a part already has that name, e.g. the
+    /// primitives of a glTF mesh with several materials or repeated nodes. Part names are
+    /// unique, so the suffix skips the names of other nodes too.
+    fn part_name(&mut self, node_name: &str) -> String {
+        let mut name = node_name.to_string();
+        let mut n = 1;
+        while self.part_names.contains(&name) || (n > 1 && self.node_names.contains(name.as_str()))
+        {
+            n += 1;
+            name = format!("{node_name} ({n})");
+        }
+        self.part_names.insert(name.clone());
+        name
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use three_d_asset::{vec3, SquareMatrix};
+
+    use super::*;
+    use crate::product::{material::MaterialCollection, shape::cube};
+
+    fn node(name: &str, transformation: Mat4, mesh: bool, children: Vec<Node>) -> Node {
+        Node {
+            name: name.to_string(),
+            children,
+            transformation,
+            geometry: mesh.then(|| Geometry::Triangles(cube(0.0, 0.0, 0.0))),
+            ..Default::default()
+        }
+    }
+
+    /// a seat and two arms below a moved "Arms" node
+    fn scene() -> Scene {
+        let up = Mat4::from_translation(vec3(0.0, 5.0, 0.0));
+        let down = Mat4::from_translation(vec3(0.0, -5.0, 0.0));
+        let arms = vec![
+            node("Arm", up, true, Vec::new()),
+            node("Arm", down, true, Vec::new()),
+        ];
+        let right = Mat4::from_translation(vec3(10.0, 0.0, 0.0));
+        Scene {
+            children: vec![
+                node("Seat", Mat4::identity(), true, Vec::new()),
+                node("Arms", right, false, arms),
+            ],
+            ..Default::default()
+        }
+    }
+
+    fn scene_def(nodes: &[&str]) -> SceneDef {
+        let option = NodeDef {
+            option: Some("arms".to_string()),
+            ..Default::default()
+        };
+        SceneDef {
+            path: "chair.glb".to_string(),
+            materials: HashMap::new(),
+            fallback: Some("metals".to_string()),
+            nodes: nodes
+                .iter()
+                .map(|n| (n.to_string(), option.clone()))
+                .collect(),
+        }
+    }
+
+    fn parts(scene: &Scene, arms: &Include) -> (Vec<ConfigPart>, Vec<Assy>) {
+        let metals: SharedMaterial = MaterialCollection::metals().into();
+        let materials = HashMap::from([("metals", metals)]);
+        let options = HashMap::from([("arms", arms.clone())]);
+        scene_def(&["Arms"])
+            .parts(scene, &materials, &options, &HashMap::new())
+            .unwrap()
+    }
+
+    #[test]
+    fn parts_follow_the_nodes() {
+        let arms = Include::optinal("Arms", false);
+        let (parts, assys) = parts(&scene(), &arms);
+
+        let names: Vec<&str> = parts.iter().map(|p| p.name()).collect();
+        assert_eq!(names, ["Seat"]);
+        assert!(matches!(parts[0].include(), Include::MustHave));
+        // "Arms" has children, so it is an assembly placed by its transform
+        assert_eq!(assys.len(), 1);
+        assert_eq!(
+            *assys[0].transform(),
+            Transform::from_translation(10.0, 0.0, 0.0)
+        );
+        let arm_parts = assys[0].parts();
+        let names: Vec<&str> = arm_parts.iter().map(|p| p.name()).collect();
+        assert_eq!(names, ["Arm", "Arm (2)"]);
+        // the option of "Arms" is inherited by its children
+        assert!(arm_parts[0].include() == &arms);
+        assert!(arm_parts[1].include() == &arms);
+
+        // the nodes place the bodies, the meshes are left as they are
+        for part in arm_parts.iter() {
+            assert_eq!(
+                part.body().shape().compute_aabb().center(),
+                vec3(0.0, 0.0, 0.0)
+            );
+        }
+        assert_eq!(
+            *arm_parts[0].body().transform(),
+            Transform::from_translation(0.0, 5.0, 0.0)
+        );
+        assert_eq!(
+            *arm_parts[1].body().transform(),
+            Transform::from_translation(0.0, -5.0, 0.0)
+        );
+        assert_eq!(assys[0].bbox().center(), vec3(10.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn suffixes_skip_node_names() {
+        let mut scene = scene();
+        let identity = Mat4::identity();
+        scene
+            .children
+            .push(node("Arm (2)", identity, true, Vec::new()));
+        let (parts, assys) = parts(&scene, &Include::optinal("Arms", false));
+        let names: Vec<&str> = parts.iter().map(|p| p.name()).collect();
+        assert_eq!(names, ["Seat", "Arm (2)"]);
+        let names: Vec<&str> = assys[0].parts().iter().map(|p| p.name()).collect();
+        assert_eq!(names, ["Arm", "Arm (3)"]);
+    }
+
+    #[test]
+    fn unfitting_transforms_are_baked() {
+        let mirror = Mat4::from_nonuniform_scale(-1.0, 1.0, 1.0);
+        let right = Mat4::from_translation(vec3(10.0, 0.0, 0.0));
+        let scene = Scene {
+            children: vec![node("Mirrored", mirror * right, true, Vec::new())],
+            ..Default::default()
+        };
+        let (parts, _) = parts(&scene, &Include::optinal("Arms", false));
+        assert!(parts[0].body().transform().is_identity());
+        let center = parts[0].body().shape().compute_aabb().center();
+        assert_eq!(center, vec3(-10.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn matrices_are_split_into_transforms() {
+        let transform = Transform::from_translation(1.0, 2.0, 3.0)
+            .with_rotation(30.0, -45.0, 60.0)
+            .with_scale(2.0, 1.0, 0.5);
+        let split = Transform::from_matrix(transform.matrix()).unwrap();
+        for (a, b) in split.rotation.iter().zip(transform.rotation) {
+            assert!((a - b).abs() < 1e-3, "{split:?}");
+        }
+        for (a, b) in split.scale.iter().zip(transform.scale) {
+            assert!((a - b).abs() < 1e-5, "{split:?}");
+        }
+        assert_eq!(split.translation, transform.translation);
+
+        let turned = Transform::IDENTITY.with_rotation(0.0, 90.0, 0.0);
+        assert!(Transform::from_matrix(turned.matrix()).is_some());
+        let shear = Mat4::from_cols(
+            vec3(1.0, 0.0, 0.0).extend(0.0),
+            vec3(1.0, 1.0, 0.0).extend(0.0),
+            vec3(0.0, 0.0, 1.0).extend(0.0),
+            vec3(0.0, 0.0, 0.0).extend(1.0),
+        );
+        assert!(Transform::from_matrix(shear).is_none());
+    }
+
+    #[test]
+    fn unknown_nodes_are_reported() {
+        let scene = scene();
+        assert_eq!(
+            scene_def(&["Arms", "Arm", "Seat"]).unknown_node(&scene),
+            None
+        );
+        assert_eq!(
+            scene_def(&["Arms", "Legs"]).unknown_node(&scene),
+            Some("Legs")
+        );
+    }
+}
@@ -0,0 +1,616 @@
+//! Undo and redo of configuration changes.
+//!
+//! Every change the user makes, together with the corrections the rules made because of it,
+//! is recorded as one step of [`Command`]s that can be reverted and reapplied.
+//! Changes made while the pointer is held down, e.g. dragging a slider or through a color
+//! picker, are merged into a single step, see [`Product::set_dragging`].
+#[cfg(feature = "render")]
+use three_d::egui::{Button, Ui};
+
+use super::{configuration::Configuration, Product};
+
+/// A single change of the configuration
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Command {
+    /// `Product::parts[part]` was opted in or out
+    OptIn { part: usize, from: bool, to: bool },
+    /// the material of `Product::parts[part]` was changed
+    PartMaterial { part: usize, from: usize, to: usize },
+    /// the custom color of `Product::parts[part]` was changed
+    PartColor {
+        part: usize,
+        from: [u8; 3],
+        to: [u8; 3],
+    },
+    /// the optional toggle `toggle` of `Product::assys[assy]` was switched
+    Include {
+        assy: usize,
+        toggle: usize,
+        from: bool,
+        to: bool,
+    },
+    /// the material collection `collection` of `Product::assys[assy]` was changed
+    AssyMaterial {
+        assy: usize,
+        collection: usize,
+        from: usize,
+        to: usize,
+    },
+    /// the custom color of the collection `collection` of `Product::assys[assy]` was changed
+    AssyColor {
+        assy: usize,
+        collection: usize,
+        from: [u8; 3],
+        to: [u8; 3],
+    },
+    /// another variant of the choice `choice` of `Product::assys[assy]` was selected
+    Variant {
+        assy: usize,
+        choice: usize,
+        from: usize,
+        to: usize,
+    },
+    /// the numeric parameter `parameter` of `Product::assys[assy]` was changed
+    Parameter {
+        assy: usize,
+        parameter: usize,
+        from: f32,
+        to: f32,
+    },
+}
+
+impl Command {
+    /// applies the change, or reverts it if `forward` is false
+    fn apply(&self, product: &mut Product, forward: bool) {
+        match *self {
+            Command::OptIn { part, from, to } => {
+                if let Some(part) = product.parts.get_mut(part) {
+                    part.set_opt_in(pick(forward, from, to));
+                }
+            }
+            Command::PartMaterial { part, from, to } => {
+                if let Some(part) = product.parts.get_mut(part) {
+                    part.materials_mut().select(pick(forward, from, to));
+                }
+            }
+            Command::PartColor { part, from, to } => {
+                if let Some(part) = product.parts.get_mut(part) {
+                    part.materials_mut()
+                        .set_custom_color(pick(forward, from, to));
+                }
+            }
+            Command::Include {
+                assy,
+                toggle,
+                from,
+                to,
+            } => {
+                let include = product
+                    .assys
+                    .get(assy)
+                    .and_then(|a| a.toggles().nth(toggle));
+                if let Some(include) = include {
+                    include.set(pick(forward, from, to));
+                }
+            }
+            Command::AssyMaterial {
+                assy,
+                collection,
+                from,
+                to,
+            } => {
+                let material = product
+                    .assys
+                    .get(assy)
+                    .and_then(|a| a.materials().get(collection));
+                if let Some(material) = material {
+                    material.borrow_mut().select(pick(forward, from, to));
+                }
+            }
+            Command::AssyColor {
+                assy,
+                collection,
+                from,
+                to,
+            } => {
+                let material = product
+                    .assys
+                    .get(assy)
+                    .and_then(|a| a.materials().get(collection));
+                if let Some(material) = material {
+                    material
+                        .borrow_mut()
+                        .set_custom_color(pick(forward, from, to));
+                }
+            }
+            Command::Variant {
+                assy,
+                choice,
+                from,
+                to,
+            } => {
+                let choice = product
+                    .assys
+                    .get(assy)
+                    .and_then(|a| a.choices().get(choice));
+                if let Some(choice) = choice {
+                    choice.borrow_mut().select(pick(forward, from, to));
+                }
+            }
+            Command::Parameter {
+                assy,
+                parameter,
+                from,
+                to,
+            } => {
+                let parameter = product
+                    .assys
+                    .get(assy)
+                    .and_then(|a| a.parameters().get(parameter));
+                if let Some(parameter) = parameter {
+                    parameter.borrow_mut().set(pick(forward, from, to));
+                }
+            }
+        }
+    }
+}
+
+impl Command {
+    /// folds `next` into self if both change the same custom color or parameter,
+    /// only used within a single drag through the color picker or a slider
+    fn merge(&mut self, next: &Command) -> bool {
+        match (self, next) {
+            (Command::PartColor { part, to, .. }, Command::PartColor { part: p, to: t, .. })
+                if part == p =>
+            {
+                *to = *t;
+                true
+            }
+            (
+                Command::AssyColor {
+                    assy,
+                    collection,
+                    to,
+                    ..
+                },
+                Command::AssyColor {
+                    assy: a,
+                    collection: c,
+                    to: t,
+                    ..
+                },
+            ) if assy == a && collection == c => {
+                *to = *t;
+                true
+            }
+            (
+                Command::Parameter {
+                    assy,
+                    parameter,
+                    to,
+                    ..
+                },
+                Command::Parameter {
+                    assy: a,
+                    parameter: p,
+                    to: t,
+                    ..
+                },
+            ) if assy == a && parameter == p => {
+                *to = *t;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+fn pick<T>(forward: bool, from: T, to: T) -> T {
+    if forward {
+        to
+    } else {
+        from
+    }
+}
+
+/// the commands that turn `from` into `to`, both must belong to the same product
+fn diff(from: &Configuration, to: &Configuration) -> Vec<Command> {
+    let mut commands = Vec::new();
+    for (part, (a, b)) in from.parts.iter().zip(to.parts.iter()).enumerate() {
+        if a.opt_in != b.opt_in {
+            commands.push(Command::OptIn {
+                part,
+                from: a.opt_in,
+                to: b.opt_in,
+            });
+        }
+        if a.material != b.material {
+            commands.push(Command::PartMaterial {
+                part,
+                from: a.material,
+                to: b.material,
+            });
+        }
+        if let (Some(from), Some(to)) = (a.color, b.color) {
+            if from != to {
+                commands.push(Command::PartColor { part, from, to });
+            }
+        }
+    }
+    for (assy, (a, b)) in from.assys.iter().zip(to.assys.iter()).enumerate() {
+        for (toggle, (from, to)) in a.includes.iter().zip(b.includes.iter()).enumerate() {
+            if from != to {
+                commands.push(Command::Include {
+                    assy,
+                    toggle,
+                    from: *from,
+                    to: *to,
+                });
+            }
+        }
+        for (collection, (from, to)) in a.materials.iter().zip(b.materials.iter()).enumerate() {
+            if from != to {
+                commands.push(Command::AssyMaterial {
+                    assy,
+                    collection,
+                    from: *from,
+                    to: *to,
+                });
+            }
+        }
+        for (collection, (from, to)) in a.colors.iter().zip(b.colors.iter()).enumerate() {
+            if let (Some(from), Some(to)) = (*from, *to) {
+                if from != to {
+                    commands.push(Command::AssyColor {
+                        assy,
+                        collection,
+                        from,
+                        to,
+                    });
+                }
+            }
+        }
+        for (choice, (from, to)) in a.choices.iter().zip(b.choices.iter()).enumerate() {
+            if from != to {
+                commands.push(Command::Variant {
+                    assy,
+                    choice,
+                    from: *from,
+                    to: *to,
+                });
+            }
+        }
+        let parameters = a.parameters.iter().zip(b.parameters.iter());
+        for (parameter, (from, to)) in parameters.enumerate() {
+            if from != to {
+                commands.push(Command::Parameter {
+                    assy,
+                    parameter,
+                    from: *from,
+                    to: *to,
+                });
+            }
+        }
+    }
+    commands
+}
+
+/// Recorded configuration changes, see [`Product::record_history`]
+#[derive(Debug, Clone, Default)]
+pub struct History {
+    undo: Vec<Box<[Command]>>,
+    redo: Vec<Box<[Command]>>,
+    /// the configuration after the last recorded step
+    last: Option<Configuration>,
+    /// whether the pointer is held down
+    dragging: bool,
+    /// whether the last step was recorded during the current drag, later changes of the
+    /// same color or parameter are merged into it
+    in_drag: bool,
+}
+
+impl History {
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+}
+
+impl Product {
+    pub fn history(&self) -> &History {
+        &self.history
+    }
+
+    /// tells the history whether the pointer is held down, changes of one color or parameter
+    /// are merged into one step until it is released
+    pub fn set_dragging(&mut self, dragging: bool) {
+        self.history.dragging = dragging;
+        if !dragging {
+            self.history.in_drag = false;
+        }
+    }
+
+    /// records the changes since the last call as one undoable step
+    pub fn record_history(&mut self) {
+        let config = self.configuration();
+        match self.history.last.as_ref() {
+            None => {}
+            Some(last) if *last == config => return,
+            Some(last) => {
+                let commands = diff(last, &config);
+                let in_drag = self.history.in_drag && self.history.redo.is_empty();
+                let merged = match (self.history.undo.last_mut(), commands.as_slice()) {
+                    (Some(step), [next]) if in_drag && step.len() == 1 => step[0].merge(next),
+                    _ => false,
+                };
+                if !commands.is_empty() {
+                    if !merged {
+                        self.history.undo.push(commands.into());
+                    }
+                    self.history.redo.clear();
+                    self.history.in_drag = self.history.dragging;
+                }
+            }
+        }
+        self.history.last = Some(config);
+    }
+
+    /// reverts the last recorded step, returns false if there is nothing to undo
+    pub fn undo(&mut self) -> bool {
+        self.record_history();
+        let Some(step) = self.history.undo.pop() else {
+            return false;
+        };
+        for command in step.iter().rev() {
+            command.apply(self, false);
+        }
+        self.history.redo.push(step);
+        self.history.last = Some(self.configuration());
+        true
+    }
+
+    /// reapplies the last undone step, returns false if there is nothing to redo
+    pub fn redo(&mut self) -> bool {
+        self.record_history();
+        let Some(step) = self.history.redo.pop() else {
+            return false;
+        };
+        for command in step.iter() {
+            command.apply(self, true);
+        }
+        self.history.undo.push(step);
+        self.history.last = Some(self.configuration());
+        true
+    }
+}
+
+#[cfg(feature = "render")]
+impl Product {
+    /// undo and redo buttons
+    pub fn add_history_controls(&mut self, ui: &mut Ui) {
+        ui.horizontal(|ui| {
+            let undo = ui.add_enabled(self.history.can_undo(), Button::new("Undo"));
+            if undo.on_hover_text("Ctrl+Z").clicked() {
+                self.undo();
+            }
+            let redo = ui.add_enabled(self.history.can_redo(), Button::new("Redo"));
+            if redo.on_hover_text("Ctrl+Y").clicked() {
+                self.redo();
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::product::{
+        material::{Material, MaterialCollection, SharedMaterial},
+        parameter::{Drive, Effect, Parameter},
+        shape::cube,
+        Assy, Body, Choice, ConfigPart, Include, Part,
+    };
+
+    /// a product with a single custom colored part
+    fn cube_product() -> Product {
+        let coat = Material::new("Powder Coat", [255, 255, 255], 0.1, 0.6);
+        let metals = MaterialCollection::metals().with_custom(coat, [].into());
+        let part = Part::new("Cube".into(), cube(0.0, 0.0, 0.0), metals, false).unwrap();
+        let mut product = Product::new("Cube", [part].into(), [].into());
+        product.record_history();
+        product
+    }
+
+    fn pick_color(product: &mut Product, rgb: [u8; 3]) {
+        let mut config = product.configuration();
+        config.parts[0].color = Some(rgb);
+        product.configure(&config).unwrap();
+        product.record_history();
+    }
+
+    /// a product with a part moved by a height slider
+    fn slider_product() -> Product {
+        let height = Parameter::new("Height", 400.0, 600.0, 450.0).shared();
+        let drives = [Drive::new(&height, Effect::Translate([0.0, 1.0, 0.0]))];
+        let body = Body::from(cube(0.0, 0.0, 0.0)).with_drives(drives.into());
+        let material = MaterialCollection::metals().into();
+        let part = ConfigPart::new("Seat".into(), body, material, Include::MustHave);
+        let assy = Assy::new([part].into()).unwrap();
+        let mut product = Product::new("Stool", [].into(), [assy].into());
+        product.record_history();
+        product
+    }
+
+    fn slide(product: &mut Product, value: f32) {
+        let mut config = product.configuration();
+        config.assys[0].parameters = vec![value];
+        product.configure(&config).unwrap();
+        product.record_history();
+    }
+
+    /// a stool with an optional cushion, optional arms, a choice of metals and of legs
+    fn stool() -> Product {
+        let cushion = Part::new(
+            "Cushion".into(),
+            cube(0.0, 1.0, 0.0),
+            MaterialCollection::fabrics(),
+            true,
+        )
+        .unwrap();
+        let metals: SharedMaterial = MaterialCollection::metals().into();
+        let legs = Choice::new("Legs", ["Four".into(), "Three".into()].into(), 0).shared();
+        let part = |name: &str, include: Include| {
+            ConfigPart::new(
+                name.into(),
+                cube(0.0, 0.0, 0.0).into(),
+                metals.clone(),
+                include,
+            )
+        };
+        let parts = [
+            part("Seat", Include::MustHave),
+            part("Arms", Include::optinal("Arms", false)),
+            part("Four Legs", Include::variant(&legs, 0)),
+            part("Three Legs", Include::variant(&legs, 1)),
+        ];
+        let assy = Assy::new(parts.into()).unwrap();
+        let mut product = Product::new("Stool", [cushion].into(), [assy].into());
+        product.record_history();
+        product
+    }
+
+    /// changes the configuration with `change` and records it
+    fn change(product: &mut Product, change: impl FnOnce(&mut Configuration)) {
+        let mut config = product.configuration();
+        change(&mut config);
+        product.configure(&config).unwrap();
+        product.record_history();
+    }
+
+    /// checks that the last step undoes to `before` and redoes to `after`
+    fn assert_undoable<T: PartialEq + std::fmt::Debug>(
+        product: &mut Product,
+        value: impl Fn(&Configuration) -> T,
+        before: T,
+        after: T,
+    ) {
+        assert_eq!(value(&product.configuration()), after);
+        assert!(product.undo());
+        assert_eq!(value(&product.configuration()), before);
+        assert!(!product.undo());
+        assert!(product.redo());
+        assert_eq!(value(&product.configuration()), after);
+        assert!(!product.redo());
+    }
+
+    #[test]
+    fn toggles_are_undone() {
+        let mut product = stool();
+        change(&mut product, |c| c.assys[0].includes[0] = true);
+        assert_undoable(&mut product, |c| c.assys[0].includes[0], false, true);
+    }
+
+    #[test]
+    fn opt_ins_are_undone() {
+        let mut product = stool();
+        change(&mut product, |c| c.parts[0].opt_in = false);
+        assert_undoable(&mut product, |c| c.parts[0].opt_in, true, false);
+    }
+
+    #[test]
+    fn materials_are_undone() {
+        let mut product = stool();
+        change(&mut product, |c| {
+            c.parts[0].material = 1;
+            c.assys[0].materials[0] = 2;
+        });
+        let materials = |c: &Configuration| (c.parts[0].material, c.assys[0].materials[0]);
+        assert_undoable(&mut product, materials, (0, 0), (1, 2));
+    }
+
+    #[test]
+    fn variants_are_undone() {
+        let mut product = stool();
+        change(&mut product, |c| c.assys[0].choices[0] = 1);
+        assert_undoable(&mut product, |c| c.assys[0].choices[0], 0, 1);
+    }
+
+    #[test]
+    fn new_changes_clear_the_redo_steps() {
+        let mut product = stool();
+        change(&mut product, |c| c.assys[0].choices[0] = 1);
+        assert!(product.undo());
+        assert!(product.history().can_redo());
+
+        change(&mut product, |c| c.assys[0].includes[0] = true);
+        assert!(!product.history().can_redo());
+        assert!(!product.redo());
+        assert_eq!(product.configuration().assys[0].choices[0], 0);
+        assert!(product.undo());
+        assert_eq!(product.configuration(), stool().configuration());
+        assert!(!product.undo());
+    }
+
+    #[test]
+    fn separate_picks_are_separate_steps() {
+        let mut product = cube_product();
+        pick_color(&mut product, [255, 0, 0]);
+        pick_color(&mut product, [0, 255, 0]);
+        assert!(product.undo());
+        assert_eq!(product.configuration().parts[0].color, Some([255, 0, 0]));
+        assert!(product.undo());
+        assert_eq!(
+            product.configuration().parts[0].color,
+            Some([255, 255, 255])
+        );
+        assert!(!product.undo());
+    }
+
+    #[test]
+    fn one_drag_is_one_step() {
+        let mut product = cube_product();
+        product.set_dragging(true);
+        for rgb in [[250, 0, 0], [240, 0, 0], [230, 0, 0]] {
+            pick_color(&mut product, rgb);
+        }
+        product.set_dragging(false);
+        // a new drag starts a new step
+        product.set_dragging(true);
+        pick_color(&mut product, [0, 0, 255]);
+        pick_color(&mut product, [0, 0, 250]);
+        product.set_dragging(false);
+
+        assert!(product.undo());
+        assert_eq!(product.configuration().parts[0].color, Some([230, 0, 0]));
+        assert!(product.undo());
+        assert_eq!(
+            product.configuration().parts[0].color,
+            Some([255, 255, 255])
+        );
+        assert!(!product.undo());
+    }
+
+    #[test]
+    fn one_slider_drag_is_one_step() {
+        let mut product = slider_product();
+        let height = |product: &Product| product.configuration().assys[0].parameters[0];
+        product.set_dragging(true);
+        for value in [460.0, 470.0, 480.0] {
+            slide(&mut product, value);
+        }
+        product.set_dragging(false);
+        // separate clicks on the slider are separate steps
+        slide(&mut product, 500.0);
+        slide(&mut product, 510.0);
+
+        assert!(product.undo());
+        assert_eq!(height(&product), 500.0);
+        assert!(product.undo());
+        assert_eq!(height(&product), 480.0);
+        assert!(product.undo());
+        assert_eq!(height(&product), 450.0);
+        assert!(!product.undo());
+    }
+}
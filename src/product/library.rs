@@ -0,0 +1,439 @@
+//! A material library shared by product definitions.
+//!
+//! Materials get a stable id, an sRGB hex color and a category, and can be grouped into
+//! named collections. Product definitions reference library materials and collections by id.
+//!
+//! ```toml
+//! version = 1
+//!
+//! [[materials]]
+//! id = "gold"
+//! name = "Gold"
+//! color = "#d4af37"
+//! metallic = 0.9
+//! roughness = 0.2
+//! category = "metal"
+//! price = 40.0
+//! textures = { albedo = "textures/gold_albedo.png", normal = "textures/gold_normal.png" }
+//!
+//! [[collections]]
+//! id = "metals"
+//! label = "Metals"
+//! materials = ["aluminium", "gold", "silver"]
+//! ```
+use std::collections::HashSet;
+use std::fmt::Display;
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    definition::{default_alpha, parent_dir, TexturesDef},
+    material::{load_textures, Material, MaterialCollection},
+    price::Price,
+};
+
+/// The library format version this build understands
+pub const LIBRARY_VERSION: u32 = 1;
+
+#[derive(Debug)]
+pub enum LibraryError {
+    Toml(toml::de::Error),
+    Json(serde_json::Error),
+    UnknownFormat(Box<str>),
+    Version(u32),
+    /// every problem found while validating the library
+    Invalid(Box<[Issue]>),
+}
+
+/// A problem with a library entry
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Issue {
+    /// the line of the entry in the library file, if it could be found
+    pub line: Option<usize>,
+    pub message: Box<str>,
+}
+
+impl Display for Issue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "line {line}: {}", self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+impl Display for LibraryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Toml(e) => write!(f, "invalid toml material library: {e}"),
+            Self::Json(e) => write!(f, "invalid json material library: {e}"),
+            Self::UnknownFormat(path) => {
+                write!(f, "unknown library format: {path}, expected .toml or .json")
+            }
+            Self::Version(v) => write!(
+                f,
+                "unsupported material library version {v}, expected {LIBRARY_VERSION}"
+            ),
+            Self::Invalid(issues) => {
+                write!(f, "invalid material library:")?;
+                for issue in issues.iter() {
+                    write!(f, "\n  {issue}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for LibraryError {}
+
+impl From<toml::de::Error> for LibraryError {
+    fn from(e: toml::de::Error) -> Self {
+        Self::Toml(e)
+    }
+}
+impl From<serde_json::Error> for LibraryError {
+    fn from(e: serde_json::Error) -> Self {
+        Self::Json(e)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LibraryMaterialDef {
+    pub id: String,
+    pub name: String,
+    /// sRGB hex color, e.g. `"#d4af37"`
+    pub color: String,
+    pub metallic: f32,
+    pub roughness: f32,
+    /// opacity, below 1 for glass and tinted panels
+    #[serde(default = "default_alpha")]
+    pub alpha: f32,
+    #[serde(default)]
+    pub category: String,
+    #[serde(default)]
+    pub price: Price,
+    /// texture paths relative to the library file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub textures: Option<TexturesDef>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LibraryCollectionDef {
+    pub id: String,
+    pub label: String,
+    /// material ids
+    pub materials: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LibraryDef {
+    pub version: u32,
+    #[serde(default)]
+    pub materials: Vec<LibraryMaterialDef>,
+    #[serde(default)]
+    pub collections: Vec<LibraryCollectionDef>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LibraryMaterial {
+    id: Box<str>,
+    category: Box<str>,
+    material: Material,
+}
+
+impl LibraryMaterial {
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+    pub fn category(&self) -> &str {
+        &self.category
+    }
+    pub fn material(&self) -> &Material {
+        &self.material
+    }
+}
+
+/// Validated materials and collections, in the order of the library file
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct MaterialLibrary {
+    materials: Vec<LibraryMaterial>,
+    /// id, label and the indices of the materials of every collection
+    collections: Vec<(Box<str>, Box<str>, Box<[usize]>)>,
+}
+
+impl MaterialLibrary {
+    pub fn from_toml(text: &str) -> Result<Self, LibraryError> {
+        let def: LibraryDef = toml::from_str(text)?;
+        Self::validate(&def, &EntryLines::of_toml(text))
+    }
+
+    /// json issues have no line numbers, serde_json does not keep spans
+    pub fn from_json(text: &str) -> Result<Self, LibraryError> {
+        let def: LibraryDef = serde_json::from_str(text)?;
+        Self::validate(&def, &EntryLines::default())
+    }
+
+    /// parses `text` as toml or json depending on the extension of `path`
+    pub fn parse(path: &str, text: &str) -> Result<Self, LibraryError> {
+        if path.ends_with(".toml") {
+            Self::from_toml(text)
+        } else if path.ends_with(".json") {
+            Self::from_json(text)
+        } else {
+            Err(LibraryError::UnknownFormat(path.into()))
+        }
+    }
+
+    /// loads a library and its textures from a local file or url
+    pub async fn load(path: &str) -> crate::Result<Self> {
+        let mut loaded = three_d_asset::io::load_async(&[path]).await?;
+        let bytes = loaded.remove(path)?;
+        let text = String::from_utf8_lossy(&bytes);
+        let mut library = Self::parse(path, &text)?;
+        let materials = library.materials.iter_mut().map(|m| &mut m.material);
+        load_textures(materials, parent_dir(path)).await?;
+        Ok(library)
+    }
+
+    /// checks `def` and builds the library, issues are reported at the lines of their entries
+    fn validate(def: &LibraryDef, lines: &EntryLines) -> Result<Self, LibraryError> {
+        if def.version != LIBRARY_VERSION {
+            return Err(LibraryError::Version(def.version));
+        }
+        let mut issues = Vec::new();
+        let mut issue = |line: Option<usize>, message: String| {
+            issues.push(Issue {
+                line,
+                message: message.into(),
+            })
+        };
+
+        let mut library = Self::default();
+        let mut seen = HashSet::new();
+        for (i, m) in def.materials.iter().enumerate() {
+            let line = lines.materials.get(i).copied();
+            if !seen.insert(m.id.as_str()) {
+                issue(line, format!("duplicate material id {}", m.id));
+                continue;
+            }
+            let Some(rgb) = rgb_from_hex(&m.color) else {
+                issue(
+                    line,
+                    format!("material {} has invalid color {}", m.id, m.color),
+                );
+                continue;
+            };
+            let mut material = Material::new(&m.name, rgb, m.metallic, m.roughness)
+                .with_alpha(m.alpha)
+                .with_price(m.price);
+            if let Some(textures) = m.textures.as_ref() {
+                material = material.with_textures(textures.into());
+            }
+            let invalid = material.invalid_values();
+            for (value, range) in invalid.iter() {
+                issue(
+                    line,
+                    format!("{value} of material {} must be {range}", m.id),
+                );
+            }
+            if !invalid.is_empty() {
+                continue;
+            }
+            library.materials.push(LibraryMaterial {
+                id: m.id.as_str().into(),
+                category: m.category.as_str().into(),
+                material,
+            });
+        }
+
+        let mut seen = HashSet::new();
+        for (i, c) in def.collections.iter().enumerate() {
+            let line = lines.collections.get(i).copied();
+            if !seen.insert(c.id.as_str()) {
+                issue(line, format!("duplicate collection id {}", c.id));
+                continue;
+            }
+            if c.materials.is_empty() {
+                issue(line, format!("collection {} has no materials", c.id));
+                continue;
+            }
+            let mut materials = Vec::with_capacity(c.materials.len());
+            for id in c.materials.iter() {
+                match library.materials.iter().position(|m| *m.id == **id) {
+                    Some(index) => materials.push(index),
+                    None if def.materials.iter().any(|m| m.id == *id) => {}
+                    None => issue(
+                        line,
+                        format!("collection {} references unknown material {id}", c.id),
+                    ),
+                }
+            }
+            library.collections.push((
+                c.id.as_str().into(),
+                c.label.as_str().into(),
+                materials.into(),
+            ));
+        }
+
+        if !issues.is_empty() {
+            return Err(LibraryError::Invalid(issues.into()));
+        }
+        Ok(library)
+    }
+
+    pub fn materials(&self) -> &[LibraryMaterial] {
+        &self.materials
+    }
+
+    pub fn material(&self, id: &str) -> Option<&Material> {
+        self.materials
+            .iter()
+            .find(|m| *m.id == *id)
+            .map(|m| &m.material)
+    }
+
+    /// a new collection with the materials of the library collection `id`
+    pub fn collection(&self, id: &str) -> Option<MaterialCollection> {
+        let (_, label, indices) = self.collections.iter().find(|(cid, ..)| **cid == *id)?;
+        let materials = indices
+            .iter()
+            .map(|i| self.materials[*i].material.clone())
+            .collect();
+        Some(MaterialCollection::new(label.clone(), materials).with_id(id))
+    }
+
+    /// the ids of the materials of the library collection `id`
+    pub fn collection_material_ids(&self, id: &str) -> Option<Vec<&str>> {
+        let (_, _, indices) = self.collections.iter().find(|(cid, ..)| **cid == *id)?;
+        Some(indices.iter().map(|i| self.materials[*i].id()).collect())
+    }
+}
+
+/// parses an sRGB hex color like `#d4af37`, the `#` is optional
+pub fn rgb_from_hex(hex: &str) -> Option<[u8; 3]> {
+    let hex = hex.strip_prefix('#').unwrap_or(hex);
+    if hex.len() != 6 || !hex.is_ascii() {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+    Some([channel(0)?, channel(2)?, channel(4)?])
+}
+
+/// The lines the material and collection entries start at, by index
+#[derive(Debug, Default)]
+struct EntryLines {
+    materials: Vec<usize>,
+    collections: Vec<usize>,
+}
+
+/// the spans of the entry ids, read in a second pass over the toml text
+#[derive(Deserialize)]
+struct EntrySpans {
+    #[serde(default)]
+    materials: Vec<EntrySpan>,
+    #[serde(default)]
+    collections: Vec<EntrySpan>,
+}
+
+#[derive(Deserialize)]
+struct EntrySpan {
+    id: toml::Spanned<String>,
+}
+
+impl EntryLines {
+    /// the lines of the `id` keys of the entries in `text`, none if it does not parse
+    fn of_toml(text: &str) -> Self {
+        let Ok(spans) = toml::from_str::<EntrySpans>(text) else {
+            return Self::default();
+        };
+        let line = |entry: &EntrySpan| text[..entry.id.span().start].matches('\n').count() + 1;
+        Self {
+            materials: spans.materials.iter().map(line).collect(),
+            collections: spans.collections.iter().map(line).collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIBRARY: &str = r##"version = 1
+
+[[materials]]
+id = "gold"
+name = "Gold"
+color = "#d4af37"
+metallic = 0.9
+roughness = 0.2
+
+[[materials]]
+# "gold" again
+id = "gold"
+name = "Gold"
+color = "#d4af37"
+metallic = 0.9
+roughness = 0.2
+
+[[materials]]
+id = "rust"
+name = "Rust"
+color = "#8b4513"
+metallic = 1.5
+roughness = 0.2
+
+[[collections]]
+id = "metals"
+label = "Metals"
+materials = ["gold", "silver"]
+"##;
+
+    fn issues(result: Result<MaterialLibrary, LibraryError>) -> Vec<Issue> {
+        match result {
+            Err(LibraryError::Invalid(issues)) => issues.into_vec(),
+            other => panic!("expected issues, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn toml_issues_have_entry_lines() {
+        let issues = issues(MaterialLibrary::from_toml(LIBRARY));
+        let lines: Vec<Option<usize>> = issues.iter().map(|i| i.line).collect();
+        assert_eq!(lines, vec![Some(12), Some(19), Some(26)]);
+        assert_eq!(&*issues[0].message, "duplicate material id gold");
+        assert_eq!(
+            &*issues[1].message,
+            "metallic of material rust must be within 0 and 1"
+        );
+        assert_eq!(
+            &*issues[2].message,
+            "collection metals references unknown material silver"
+        );
+    }
+
+    #[test]
+    fn json_issues_have_no_lines() {
+        let json = r#"{
+            "version": 1,
+            "materials": [{ "id": "gold", "name": "Gold", "color": "gold",
+                            "metallic": 0.9, "roughness": 0.2 }]
+        }"#;
+        let issues = issues(MaterialLibrary::from_json(json));
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].line, None);
+    }
+
+    #[test]
+    fn collections_keep_material_ids() {
+        let text = LIBRARY.replace("# \"gold\" again\nid = \"gold\"", "id = \"silver\"");
+        let text = text.replace("metallic = 1.5", "metallic = 1.0");
+        let library = MaterialLibrary::from_toml(&text).unwrap();
+        assert_eq!(
+            library.collection_material_ids("metals"),
+            Some(vec!["gold", "silver"])
+        );
+        assert_eq!(library.collection("metals").unwrap().id(), "metals");
+        assert_eq!(library.collection_material_ids("fabrics"), None);
+    }
+}
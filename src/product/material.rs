@@ -5,8 +5,140 @@ use std::ops::{Deref, DerefMut, Index};
 use std::rc::Rc;
 use std::sync::atomic::AtomicPtr;
 
+#[cfg(feature = "render")]
 use three_d::egui::Color32;
-use three_d_asset::PbrMaterial;
+use three_d_asset::{PbrMaterial, Srgba, Texture2D};
+
+use super::{definition::resolve, price::Price};
+
+/// A texture map of a material, compared and hashed by its path
+#[derive(Clone, Debug)]
+pub struct TextureMap {
+    path: Box<str>,
+    /// `None` until loaded with [`load_textures`]
+    texture: Option<Rc<Texture2D>>,
+}
+
+impl TextureMap {
+    pub fn new(path: &str) -> Self {
+        Self {
+            path: path.into(),
+            texture: None,
+        }
+    }
+    /// a map of a texture that is already loaded, e.g. from a glTF, `path` only identifies it
+    fn loaded(path: String, texture: &Texture2D) -> Self {
+        Self {
+            path: path.into(),
+            texture: Some(Rc::new(texture.clone())),
+        }
+    }
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+    pub fn texture(&self) -> Option<&Texture2D> {
+        self.texture.as_deref()
+    }
+}
+
+impl PartialEq for TextureMap {
+    fn eq(&self, other: &Self) -> bool {
+        self.path == other.path
+    }
+}
+
+impl Hash for TextureMap {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.path.hash(state);
+    }
+}
+
+/// The optional texture maps of a material
+#[derive(Clone, Debug, PartialEq)]
+pub struct Textures {
+    pub albedo: Option<TextureMap>,
+    pub normal: Option<TextureMap>,
+    /// roughness in the green and metallic in the blue channel, as in glTF
+    pub metallic_roughness: Option<TextureMap>,
+    pub occlusion: Option<TextureMap>,
+    /// how often the textures repeat per uv unit
+    pub scale: f32,
+}
+
+impl Default for Textures {
+    fn default() -> Self {
+        Self {
+            albedo: None,
+            normal: None,
+            metallic_roughness: None,
+            occlusion: None,
+            scale: 1.0,
+        }
+    }
+}
+
+impl Textures {
+    fn maps(&self) -> [&Option<TextureMap>; 4] {
+        [
+            &self.albedo,
+            &self.normal,
+            &self.metallic_roughness,
+            &self.occlusion,
+        ]
+    }
+    fn maps_mut(&mut self) -> [&mut Option<TextureMap>; 4] {
+        [
+            &mut self.albedo,
+            &mut self.normal,
+            &mut self.metallic_roughness,
+            &mut self.occlusion,
+        ]
+    }
+}
+
+impl Hash for Textures {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.maps().hash(state);
+        self.scale.to_bits().hash(state);
+    }
+}
+
+/// loads every texture of `materials` that is not loaded yet,
+/// relative paths are resolved against `base`
+pub(crate) async fn load_textures<'a>(
+    materials: impl Iterator<Item = &'a mut Material>,
+    base: &str,
+) -> crate::Result<()> {
+    let mut maps: Vec<&mut TextureMap> = materials
+        .filter_map(|m| m.textures.as_mut())
+        .flat_map(|t| t.maps_mut())
+        .filter_map(|map| map.as_mut())
+        .filter(|map| map.texture.is_none())
+        .collect();
+    if maps.is_empty() {
+        return Ok(());
+    }
+    for map in maps.iter_mut() {
+        map.path = resolve(base, &map.path).into();
+    }
+    let mut paths: Vec<&str> = maps.iter().map(|map| map.path.as_ref()).collect();
+    paths.sort();
+    paths.dedup();
+    let mut loaded = three_d_asset::io::load_async(&paths).await?;
+    let mut textures: Vec<(Box<str>, Rc<Texture2D>)> = Vec::new();
+    for map in maps {
+        let texture = match textures.iter().find(|(path, _)| *path == map.path) {
+            Some((_, texture)) => texture.clone(),
+            None => {
+                let texture = Rc::new(loaded.deserialize::<Texture2D>(map.path.as_ref())?);
+                textures.push((map.path.clone(), texture.clone()));
+                texture
+            }
+        };
+        map.texture = Some(texture);
+    }
+    Ok(())
+}
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct Material {
@@ -14,17 +146,49 @@ pub struct Material {
     rgb: [u8; 3],
     metallic: f32,
     roughness: f32,
+    /// opacity, 1 is opaque and lower values let light through like glass or acrylic
+    alpha: f32,
+    /// added to the price of every part made of self
+    price: Price,
+    textures: Option<Textures>,
 }
 impl Material {
-    fn not_nan(&self) -> bool {
+    pub(crate) fn not_nan(&self) -> bool {
         if self.metallic.is_nan() {
             return false;
         };
         if self.roughness.is_nan() {
             return false;
         };
+        if self.alpha.is_nan() {
+            return false;
+        };
+        if self.textures.as_ref().is_some_and(|t| t.scale.is_nan()) {
+            return false;
+        }
         true
     }
+
+    /// the values of self that are NaN or out of range, with the range they have to be in
+    pub(crate) fn invalid_values(&self) -> Vec<(&'static str, &'static str)> {
+        let mut invalid = Vec::new();
+        let values = [
+            ("metallic", self.metallic),
+            ("roughness", self.roughness),
+            ("alpha", self.alpha),
+        ];
+        for (name, value) in values {
+            if !(0.0..=1.0).contains(&value) {
+                invalid.push((name, "within 0 and 1"));
+            }
+        }
+        if let Some(textures) = self.textures.as_ref() {
+            if !(textures.scale.is_finite() && textures.scale > 0.0) {
+                invalid.push(("texture scale", "above 0"));
+            }
+        }
+        invalid
+    }
 }
 
 impl Eq for Material {}
@@ -36,32 +200,89 @@ impl Hash for Material {
         debug_assert!(self.not_nan(), "NaN is not allowed in material sets");
         self.metallic.to_bits().hash(state);
         self.roughness.to_bits().hash(state);
+        self.alpha.to_bits().hash(state);
         self.name.hash(state);
         self.rgb.hash(state);
+        self.price.hash(state);
+        self.textures.hash(state);
     }
 }
 
 impl Material {
-    fn new(name: &str, rgb: [u8; 3], metallic: f32, roughness: f32) -> Self {
+    pub fn new(name: &str, rgb: [u8; 3], metallic: f32, roughness: f32) -> Self {
         Self {
             name: name.into(),
             rgb,
             metallic,
             roughness,
+            alpha: 1.0,
+            price: Price::ZERO,
+            textures: None,
         }
     }
+    pub fn with_alpha(mut self, alpha: f32) -> Self {
+        self.alpha = alpha;
+        self
+    }
+    pub fn alpha(&self) -> f32 {
+        self.alpha
+    }
+    /// whether self has to be blended with what is behind it
+    pub fn is_transparent(&self) -> bool {
+        self.alpha < 1.0
+    }
+    /// the color of self with its alpha
+    pub fn srgba(&self) -> Srgba {
+        let [r, g, b] = self.rgb;
+        Srgba::new(r, g, b, (self.alpha.clamp(0.0, 1.0) * 255.0).round() as u8)
+    }
+    pub fn with_price(mut self, price: Price) -> Self {
+        self.price = price;
+        self
+    }
+    pub fn with_textures(mut self, textures: Textures) -> Self {
+        self.textures = Some(textures);
+        self
+    }
+    pub fn textures(&self) -> Option<&Textures> {
+        self.textures.as_ref()
+    }
+    /// how often the textures of self repeat per uv unit
+    pub fn texture_scale(&self) -> f32 {
+        self.textures.as_ref().map_or(1.0, |t| t.scale)
+    }
+    pub fn price(&self) -> Price {
+        self.price
+    }
+    pub fn name(&self) -> &str {
+        &self.name
+    }
     pub fn rgb(&self) -> [u8; 3] {
         self.rgb
     }
-    #[allow(dead_code)]
+    /// the color of self as `#rrggbb`
+    pub fn hex(&self) -> String {
+        let [r, g, b] = self.rgb;
+        format!("#{r:02x}{g:02x}{b:02x}")
+    }
+    #[cfg(feature = "render")]
     pub fn color32(&self) -> Color32 {
         Color32::from_rgb(self.rgb[0], self.rgb[1], self.rgb[2])
     }
     pub fn pbr(&self) -> PbrMaterial {
+        let texture = |map: fn(&Textures) -> &Option<TextureMap>| {
+            let textures = self.textures.as_ref()?;
+            map(textures).as_ref()?.texture().cloned()
+        };
         PbrMaterial {
-            albedo: self.rgb.into(),
+            name: self.name.to_string(),
+            albedo: self.srgba(),
+            albedo_texture: texture(|t| &t.albedo),
             metallic: self.metallic,
             roughness: self.roughness,
+            metallic_roughness_texture: texture(|t| &t.metallic_roughness),
+            normal_texture: texture(|t| &t.normal),
+            occlusion_texture: texture(|t| &t.occlusion),
             ..Default::default()
         }
     }
@@ -110,6 +331,36 @@ impl Material {
     }
 }
 
+/// the color, metallic, roughness, alpha and texture maps of a loaded material
+impl From<&PbrMaterial> for Material {
+    fn from(pbr: &PbrMaterial) -> Self {
+        let Srgba { r, g, b, a } = pbr.albedo;
+        let material = Material::new(&pbr.name, [r, g, b], pbr.metallic, pbr.roughness)
+            .with_alpha(a as f32 / 255.0);
+        // embedded textures have no name, they are told apart by material and map
+        let map = |kind: &str, texture: &Option<Texture2D>| {
+            let texture = texture.as_ref()?;
+            let path = match texture.name.as_str() {
+                "" => format!("{}#{kind}", pbr.name),
+                name => name.to_string(),
+            };
+            Some(TextureMap::loaded(path, texture))
+        };
+        let textures = Textures {
+            albedo: map("albedo", &pbr.albedo_texture),
+            normal: map("normal", &pbr.normal_texture),
+            metallic_roughness: map("metallic_roughness", &pbr.metallic_roughness_texture),
+            occlusion: map("occlusion", &pbr.occlusion_texture),
+            ..Default::default()
+        };
+        if textures.maps().iter().any(|map| map.is_some()) {
+            material.with_textures(textures)
+        } else {
+            material
+        }
+    }
+}
+
 impl Display for Material {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", self.name)
@@ -119,9 +370,15 @@ type Materials = Box<[Material]>;
 
 #[derive(Debug, Clone, Hash, PartialEq)]
 pub struct MaterialCollection {
+    /// stable id rules refer to, the label if none is given
+    id: Box<str>,
     label: Box<str>,
     options: Materials,
     pub current_material: usize,
+    /// index of the entry in `options` whose color the customer picks
+    custom: Option<usize>,
+    /// colors the custom entry is limited to, any color if empty
+    palette: Box<[[u8; 3]]>,
 }
 impl Index<usize> for MaterialCollection {
     type Output = Material;
@@ -134,12 +391,31 @@ impl Index<usize> for MaterialCollection {
 impl MaterialCollection {
     pub fn new(label: Box<str>, options: Materials) -> Self {
         Self {
+            id: label.clone(),
             options,
             label,
             current_material: 0,
+            custom: None,
+            palette: [].into(),
         }
     }
 
+    /// adds `material` as an entry the customer can give any color,
+    /// or one of `palette` if it is not empty
+    pub fn with_custom(mut self, material: Material, palette: Box<[[u8; 3]]>) -> Self {
+        let mut options = std::mem::take(&mut self.options).into_vec();
+        self.custom = Some(options.len());
+        options.push(material);
+        self.options = options.into();
+        self.palette = palette;
+        self
+    }
+
+    pub fn with_id(mut self, id: impl Into<Box<str>>) -> Self {
+        self.id = id.into();
+        self
+    }
+
     pub fn mono(material: Material) -> Self {
         MaterialCollection::new(material.name.clone().into(), [material].into())
     }
@@ -147,6 +423,9 @@ impl MaterialCollection {
     pub fn current(&self) -> &Material {
         &self.options[self.current_material]
     }
+    pub fn id(&self) -> &str {
+        &self.id
+    }
     pub fn label(&self) -> &str {
         &self.label
     }
@@ -157,6 +436,48 @@ impl MaterialCollection {
     pub fn len(&self) -> usize {
         self.options.len()
     }
+
+    pub(crate) fn options_mut(&mut self) -> &mut [Material] {
+        &mut self.options
+    }
+
+    /// selects the material at `index`, returns false if it is out of range
+    pub fn select(&mut self, index: usize) -> bool {
+        if index >= self.options.len() {
+            return false;
+        }
+        self.current_material = index;
+        true
+    }
+
+    pub fn is_custom(&self, index: usize) -> bool {
+        self.custom == Some(index)
+    }
+    /// whether the current material is the custom color entry
+    pub fn custom_selected(&self) -> bool {
+        self.is_custom(self.current_material)
+    }
+    pub fn palette(&self) -> &[[u8; 3]] {
+        &self.palette
+    }
+    /// the picked color of the custom entry, `None` if self has none
+    pub fn custom_color(&self) -> Option<[u8; 3]> {
+        self.custom.map(|i| self.options[i].rgb)
+    }
+    /// whether `rgb` can be picked for the custom entry
+    pub fn allows_color(&self, rgb: [u8; 3]) -> bool {
+        self.custom.is_some() && (self.palette.is_empty() || self.palette.contains(&rgb))
+    }
+    /// sets the color of the custom entry, returns false if it is not allowed
+    pub fn set_custom_color(&mut self, rgb: [u8; 3]) -> bool {
+        match self.custom {
+            Some(i) if self.allows_color(rgb) => {
+                self.options[i].rgb = rgb;
+                true
+            }
+            _ => false,
+        }
+    }
 }
 
 impl From<Material> for MaterialCollection {
@@ -215,3 +536,42 @@ impl DerefMut for SharedMaterial {
         &mut self.material
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn loaded_materials_keep_their_textures() {
+        let texture = |name: &str| Texture2D {
+            name: name.to_string(),
+            ..Default::default()
+        };
+        let pbr = PbrMaterial {
+            name: "Oak".to_string(),
+            albedo: Srgba::new(200, 150, 100, 128),
+            albedo_texture: Some(texture("oak.png")),
+            normal_texture: Some(texture("")),
+            metallic: 0.0,
+            roughness: 0.7,
+            ..Default::default()
+        };
+        let material = Material::from(&pbr);
+        assert_eq!(material.rgb(), [200, 150, 100]);
+        assert_eq!(material.alpha(), 128.0 / 255.0);
+        assert_eq!(material.roughness(), 0.7);
+
+        let textures = material.textures().unwrap();
+        let path = |map: &Option<TextureMap>| map.as_ref().map(|m| m.path().to_string());
+        assert_eq!(path(&textures.albedo).as_deref(), Some("oak.png"));
+        assert_eq!(path(&textures.normal).as_deref(), Some("Oak#normal"));
+        assert_eq!(textures.metallic_roughness, None);
+        assert!(textures.albedo.as_ref().unwrap().texture().is_some());
+
+        let plain = PbrMaterial {
+            name: "Steel".to_string(),
+            ..Default::default()
+        };
+        assert!(Material::from(&plain).textures().is_none());
+    }
+}
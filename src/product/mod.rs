@@ -1,76 +1,95 @@
 mod assembly;
+pub mod bom;
+pub mod code;
 mod component;
+pub mod configuration;
+pub mod definition;
+pub mod gltf;
+pub mod history;
+pub mod library;
 pub mod material;
+#[cfg(feature = "render")]
+mod model;
+mod obj;
+pub mod parameter;
 mod part;
-mod shape;
+pub mod preset;
+pub mod price;
+pub mod rules;
+pub mod shape;
+#[cfg(feature = "render")]
+mod swatch;
+pub mod transform;
 
+pub use assembly::{Assy, Choice, ConfigPart, Include, SharedChoice};
+pub use component::Body;
+pub use configuration::Configuration;
+use history::History;
+#[cfg(feature = "render")]
+use log::warn;
+#[cfg(feature = "render")]
+pub use model::Model;
 pub use part::Part;
-use three_d::{egui::Ui, AxisAlignedBoundingBox as AABB, Context, Gm, Mesh, PhysicalMaterial};
+use preset::Preset;
+use rules::Rules;
+#[cfg(feature = "render")]
+use three_d::{egui::Ui, Context};
+use three_d_asset::AxisAlignedBoundingBox as AABB;
 
-use assembly::Assy;
-
-pub type PbrModel = Gm<Mesh, PhysicalMaterial>;
+use crate::Error;
 
 pub struct Product {
     name: Box<str>,
     parts: Box<[Part]>,
     assys: Box<[Assy]>,
+    currency: Box<str>,
+    rules: Rules,
+    rule_notes: Box<[Box<str>]>,
+    rules_checked: Option<Configuration>,
+    history: History,
+    presets: Box<[Preset]>,
+    /// index of the last applied preset
+    preset: Option<usize>,
 }
 
-impl<'a> Product {
+impl Product {
     fn new(name: &str, parts: Box<[Part]>, assys: Box<[Assy]>) -> Self {
         Self {
             name: name.into(),
             parts,
             assys,
+            currency: "".into(),
+            rules: [].into(),
+            rule_notes: [].into(),
+            rules_checked: None,
+            history: History::default(),
+            presets: [].into(),
+            preset: None,
         }
     }
 
-    pub fn init(&mut self, ctx: &Context) {
-        for part in self.parts.iter_mut() {
-            part.init(ctx);
-        }
-        for assy in self.assys.iter_mut() {
-            assy.init(ctx);
-        }
+    pub fn name(&self) -> &str {
+        &self.name
     }
 
-    #[allow(dead_code)]
-    pub async fn placeholder() -> Self {
-        // let parts = [Component::placeholder1(), Component::placeholder2()].into();
-        let parts = Part::placeholder_chair().await;
-        Self::new("Chair (tm)", parts, [].into())
-    }
-
-    pub async fn assy_dummy() -> Self {
-        let assy = Assy::placeholder_chair().await;
-        Self::new("Dummy", [].into(), [assy].into())
+    pub fn parts(&self) -> &[Part] {
+        &self.parts
     }
 
-    pub fn objects(&'a self) -> impl Iterator<Item = &'a (dyn three_d::Object + 'a)> {
-        let assy_objects = self.assys.iter().flat_map(|assy| assy.objects());
-        let part_objects = self.parts.iter().filter_map(move |part| part.object());
-        assy_objects.chain(part_objects)
+    pub fn assys(&self) -> &[Assy] {
+        &self.assys
     }
 
-    pub fn add_controls(&mut self, ui: &mut Ui) {
-        ui.heading(self.name.as_ref());
-        for part in self.parts.iter_mut() {
-            ui.add_space(10.0);
-            part.add_controls(ui)
-        }
-        for assy in self.assys.iter_mut() {
-            assy.add_controls(ui);
-        }
+    #[allow(dead_code)]
+    pub async fn placeholder() -> crate::Result<Self> {
+        // let parts = [Component::placeholder1(), Component::placeholder2()].into();
+        let parts = Part::placeholder_chair().await?;
+        Ok(Self::new("Chair (tm)", parts, [].into()))
     }
 
-    pub fn update(&mut self) {
-        for part in self.parts.iter_mut() {
-            part.update();
-        }
-        for assy in self.assys.iter_mut() {
-            assy.update();
-        }
+    pub async fn assy_dummy() -> crate::Result<Self> {
+        let assy = Assy::placeholder_chair().await?;
+        Ok(Self::new("Dummy", [].into(), [assy].into()))
     }
 
     fn parts_bb(&self) -> Option<AABB> {
@@ -78,11 +97,11 @@ impl<'a> Product {
         if len == 0 {
             return None;
         }
-        let mut bb = self.parts[0].shape().compute_aabb();
+        let mut bb = self.parts[0].bounding_box();
         if len == 1 {
             return Some(bb);
         }
-        for other_bb in self.parts[1..].iter().map(|p| p.shape().compute_aabb()) {
+        for other_bb in self.parts[1..].iter().map(|p| p.bounding_box()) {
             bb.expand_with_aabb(&other_bb);
         }
         Some(bb)
@@ -101,16 +120,71 @@ impl<'a> Product {
         }
         Some(bb)
     }
-    pub fn bbox(&self) -> AABB {
+    pub fn bbox(&self) -> crate::Result<AABB> {
         let bb1 = self.parts_bb();
         let bb2 = self.assys_bb();
         match (bb1, bb2) {
-            (None, None) => panic!("product has no parts nor assemblies"),
-            (None, Some(bb)) | (Some(bb), None) => bb,
+            (None, None) => Err(Error::EmptyProduct),
+            (None, Some(bb)) | (Some(bb), None) => Ok(bb),
             (Some(mut bb), Some(bb2)) => {
                 bb.expand_with_aabb(&bb2);
-                bb
+                Ok(bb)
             }
         }
     }
 }
+
+#[cfg(feature = "render")]
+impl<'a> Product {
+    pub fn init(&mut self, ctx: &Context) {
+        for part in self.parts.iter_mut() {
+            part.init(ctx);
+        }
+        for assy in self.assys.iter_mut() {
+            assy.init(ctx);
+        }
+    }
+
+    pub fn objects(&'a self) -> impl Iterator<Item = &'a (dyn three_d::Object + 'a)> {
+        let assy_objects = self.assys.iter().flat_map(|assy| assy.objects());
+        let part_objects = self
+            .parts
+            .iter()
+            .filter_map(move |part| match part.object() {
+                Ok(object) => object,
+                Err(e) => {
+                    warn!("skipping part: {}", e);
+                    None
+                }
+            });
+        assy_objects.chain(part_objects)
+    }
+
+    pub fn add_controls(&mut self, ui: &mut Ui) {
+        ui.heading(self.name.as_ref());
+        self.add_preset_ui(ui);
+        let warn_color = ui.visuals().warn_fg_color;
+        for note in self.rule_notes.iter() {
+            ui.colored_label(warn_color, note.as_ref());
+        }
+        let blocked = self.blocked();
+        for part in self.parts.iter_mut() {
+            ui.add_space(10.0);
+            part.add_controls(ui, &blocked)
+        }
+        for assy in self.assys.iter_mut() {
+            assy.add_controls(ui, &blocked);
+        }
+    }
+
+    pub fn update(&mut self) {
+        self.apply_rules();
+        self.record_history();
+        for part in self.parts.iter_mut() {
+            part.update();
+        }
+        for assy in self.assys.iter_mut() {
+            assy.update();
+        }
+    }
+}
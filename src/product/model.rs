@@ -0,0 +1,176 @@
+//! The gpu side of a body or part.
+use std::borrow::Cow;
+
+use three_d::{Context, Gm, InstancedMesh, Instances, Mat3, Mat4, Mesh, Object, PhysicalMaterial};
+use three_d_asset::TriMesh;
+
+use super::material::Material;
+
+/// The gpu mesh, drawn once or once per instance
+enum Meshes {
+    Single(Gm<Mesh, PhysicalMaterial>),
+    Instanced {
+        gm: Gm<InstancedMesh, PhysicalMaterial>,
+        /// placement of every instance relative to the mesh transformation
+        instances: Box<[Mat4]>,
+    },
+}
+
+/// A mesh on the gpu with the physical material of the [`Material`] it currently shows
+pub struct Model {
+    meshes: Meshes,
+    context: Context,
+    /// the material the gpu material was last built from
+    material: Material,
+    /// whether the uploaded mesh has tangents, or can not get any without normals or uvs
+    tangents: bool,
+}
+
+impl Model {
+    pub fn new(ctx: &Context, shape: &TriMesh, material: &Material) -> Self {
+        let shape = with_tangents(shape, material);
+        let gm = Gm::new(Mesh::new(ctx, &shape), material.physical(ctx));
+        Self::with_meshes(ctx, Meshes::Single(gm), material, has_tangents(&shape))
+    }
+
+    /// one mesh drawn at every transformation of `instances` in a single draw call
+    pub fn instanced(
+        ctx: &Context,
+        shape: &TriMesh,
+        material: &Material,
+        instances: &[Mat4],
+    ) -> Self {
+        let shape = with_tangents(shape, material);
+        let transformations = Instances {
+            transformations: instances.to_vec(),
+            ..Default::default()
+        };
+        let meshes = Meshes::Instanced {
+            gm: Gm::new(
+                InstancedMesh::new(ctx, &transformations, &shape),
+                material.physical(ctx),
+            ),
+            instances: instances.into(),
+        };
+        Self::with_meshes(ctx, meshes, material, has_tangents(&shape))
+    }
+
+    fn with_meshes(ctx: &Context, meshes: Meshes, material: &Material, tangents: bool) -> Self {
+        let mut model = Self {
+            meshes,
+            context: ctx.clone(),
+            material: material.clone(),
+            tangents,
+        };
+        model.set_texture_scale();
+        model
+    }
+
+    pub fn object(&self) -> &dyn Object {
+        match &self.meshes {
+            Meshes::Single(gm) => gm,
+            Meshes::Instanced { gm, .. } => gm,
+        }
+    }
+
+    /// places the mesh, see [`super::transform::Transform::matrix`]
+    pub fn set_transformation(&mut self, transformation: Mat4) {
+        match &mut self.meshes {
+            Meshes::Single(gm) => gm.geometry.set_transformation(transformation),
+            Meshes::Instanced { gm, .. } => gm.geometry.set_transformation(transformation),
+        }
+    }
+
+    /// uploads another mesh, e.g. a deformed one, the transformation has to be set again
+    pub fn set_shape(&mut self, shape: &TriMesh) {
+        let shape = with_tangents(shape, &self.material);
+        match &mut self.meshes {
+            Meshes::Single(gm) => gm.geometry = Mesh::new(&self.context, &shape),
+            Meshes::Instanced { gm, instances } => {
+                let transformations = Instances {
+                    transformations: instances.to_vec(),
+                    ..Default::default()
+                };
+                gm.geometry = InstancedMesh::new(&self.context, &transformations, &shape);
+            }
+        }
+        self.tangents = has_tangents(&shape);
+        self.set_texture_scale();
+    }
+
+    /// whether the current material has a normal map the uploaded mesh has no tangents for,
+    /// the mesh has to be set again with [`Model::set_shape`]
+    pub fn lacks_tangents(&self) -> bool {
+        has_normal_map(&self.material) && !self.tangents
+    }
+
+    fn physical_mut(&mut self) -> &mut PhysicalMaterial {
+        match &mut self.meshes {
+            Meshes::Single(gm) => &mut gm.material,
+            Meshes::Instanced { gm, .. } => &mut gm.material,
+        }
+    }
+
+    /// shows `material`, textures are only uploaded again if they changed
+    pub fn set_material(&mut self, material: &Material) {
+        if self.material == *material {
+            return;
+        }
+        let same_kind = self.material.is_transparent() == material.is_transparent();
+        let same_scale = self.material.texture_scale() == material.texture_scale();
+        if same_kind && self.material.textures() == material.textures() {
+            let physical = self.physical_mut();
+            physical.albedo = material.srgba();
+            physical.metallic = material.metallic();
+            physical.roughness = material.roughness();
+        } else {
+            *self.physical_mut() = material.physical(&self.context);
+        }
+        self.material = material.clone();
+        if !same_scale {
+            self.set_texture_scale();
+        }
+    }
+
+    fn set_texture_scale(&mut self) {
+        let texture_transform = Mat3::from_scale(self.material.texture_scale());
+        match &mut self.meshes {
+            Meshes::Single(gm) => gm.geometry.set_texture_transform(texture_transform),
+            // instances carry their own texture transformation
+            Meshes::Instanced { gm, instances } => gm.geometry.set_instances(&Instances {
+                transformations: instances.to_vec(),
+                texture_transformations: Some(vec![texture_transform; instances.len()]),
+                ..Default::default()
+            }),
+        }
+    }
+}
+
+fn has_normal_map(material: &Material) -> bool {
+    material.textures().is_some_and(|t| t.normal.is_some())
+}
+
+fn has_tangents(shape: &TriMesh) -> bool {
+    shape.tangents.is_some() || shape.normals.is_none() || shape.uvs.is_none()
+}
+
+/// `shape` with tangents if `material` has a normal map, three-d needs them to apply it
+fn with_tangents<'a>(shape: &'a TriMesh, material: &Material) -> Cow<'a, TriMesh> {
+    if !has_normal_map(material) || has_tangents(shape) {
+        return Cow::Borrowed(shape);
+    }
+    let mut shape = shape.clone();
+    shape.compute_tangents();
+    Cow::Owned(shape)
+}
+
+impl Material {
+    /// a gpu material with the textures of self uploaded, blended if self is transparent
+    pub fn physical(&self, ctx: &Context) -> PhysicalMaterial {
+        if self.is_transparent() {
+            PhysicalMaterial::new_transparent(ctx, &self.pbr())
+        } else {
+            PhysicalMaterial::new_opaque(ctx, &self.pbr())
+        }
+    }
+}
@@ -0,0 +1,543 @@
+//! Splitting a single OBJ file into a mesh per object or group.
+//!
+//! Every `o` and `g` statement starts a part named after it, faces of the same name are
+//! collected into one mesh wherever they appear in the file. A group using several `usemtl`
+//! slots becomes a node with a child per slot named "Group (slot)", so the settings of the
+//! group apply to all of them. A [scene](super::gltf) maps the slots onto material collections
+//! just like glTF materials.
+//!
+//! Slots default to the values of the MTL files named by `mtllib`. Phong materials have no
+//! metallic or roughness, so they are estimated: `Ns` is turned into a roughness, and a
+//! specular color `Ks` brighter than the diffuse color `Kd` makes a material metallic.
+//! The `Pm` and `Pr` extensions, written by Blender among others, are used as is.
+//!
+//! ```text
+//! o Frame
+//! usemtl Steel
+//! f 1/1/1 2/2/1 3/3/1
+//! g Arms
+//! f 4/4/2 5/5/2 6/6/2
+//! ```
+use std::collections::HashMap;
+
+use log::warn;
+use three_d_asset::{
+    io::RawAssets, vec2, vec3, Geometry, Indices, Node, PbrMaterial, Positions, Scene, TriMesh,
+    Vec2, Vec3,
+};
+
+use super::{
+    definition::{parent_dir, resolve as resolve_path, DefinitionError},
+    material::Material,
+};
+
+/// the position, uv and normal index of a face corner
+type Corner = (usize, Option<usize>, Option<usize>);
+
+/// the name given to faces before the first `o` or `g`
+const DEFAULT_GROUP: &str = "default";
+
+struct Group {
+    material: Option<usize>,
+    /// three corners per triangle
+    corners: Vec<Corner>,
+}
+
+/// a scene with a node per object or group of the OBJ at `path`,
+/// `loaded` has to hold the OBJ and the MTL files it uses
+pub(crate) fn scene_from_obj(path: &str, loaded: &RawAssets) -> crate::Result<Scene> {
+    let text = String::from_utf8_lossy(loaded.get(path)?);
+    let invalid = |line: usize| DefinitionError::InvalidObj {
+        path: path.into(),
+        line,
+    };
+    let mut positions: Vec<Vec3> = Vec::new();
+    let mut uvs: Vec<Vec2> = Vec::new();
+    let mut normals: Vec<Vec3> = Vec::new();
+    let mut materials: Vec<String> = Vec::new();
+    let mut libraries: Vec<String> = Vec::new();
+    let mut groups: Vec<(String, Group)> = Vec::new();
+
+    let mut object = DEFAULT_GROUP.to_string();
+    let mut name = object.clone();
+    let mut current: Option<usize> = None;
+    let mut material: Option<usize> = None;
+    for (i, line) in text.lines().enumerate() {
+        let mut words = line.split_whitespace();
+        match words.next() {
+            Some("v") => positions.push(vector(&mut words).ok_or_else(|| invalid(i + 1))?),
+            Some("vn") => normals.push(vector(&mut words).ok_or_else(|| invalid(i + 1))?),
+            Some("vt") => {
+                let u = float(words.next()).ok_or_else(|| invalid(i + 1))?;
+                let v = float(words.next()).unwrap_or(0.0);
+                uvs.push(vec2(u, 1.0 - v));
+            }
+            Some("o") => {
+                object = rest(words);
+                name = object.clone();
+                current = None;
+            }
+            Some("g") => {
+                let group = rest(words);
+                name = if group.is_empty() {
+                    object.clone()
+                } else {
+                    group
+                };
+                current = None;
+            }
+            Some("mtllib") => {
+                libraries.extend(words.map(|lib| resolve_path(parent_dir(path), lib)))
+            }
+            Some("usemtl") => {
+                let slot = rest(words);
+                let index = match materials.iter().position(|m| *m == slot) {
+                    Some(index) => index,
+                    None => {
+                        materials.push(slot);
+                        materials.len() - 1
+                    }
+                };
+                material = Some(index);
+                current = None;
+            }
+            Some("f") => {
+                let counts = [positions.len(), uvs.len(), normals.len()];
+                let face = words
+                    .map(|word| corner(word, counts))
+                    .collect::<Option<Vec<_>>>()
+                    .filter(|face| face.len() >= 3)
+                    .ok_or_else(|| invalid(i + 1))?;
+                let index = *current.get_or_insert_with(|| {
+                    let same = |(n, g): &(String, Group)| *n == name && g.material == material;
+                    match groups.iter().position(same) {
+                        Some(index) => index,
+                        None => {
+                            let group = Group {
+                                material,
+                                corners: Vec::new(),
+                            };
+                            groups.push((name.clone(), group));
+                            groups.len() - 1
+                        }
+                    }
+                });
+                let group = &mut groups[index].1;
+                for k in 1..face.len() - 1 {
+                    group.corners.extend([face[0], face[k], face[k + 1]]);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let mut children: Vec<Node> = Vec::new();
+    for (name, _) in groups.iter() {
+        if children.iter().any(|node| node.name == *name) {
+            continue;
+        }
+        let mut nodes: Vec<Node> = groups
+            .iter()
+            .filter(|(n, _)| n == name)
+            .map(|(_, group)| Node {
+                name: match group.material {
+                    Some(slot) => format!("{name} ({})", materials[slot]),
+                    None => name.clone(),
+                },
+                geometry: Some(Geometry::Triangles(group.mesh(&positions, &uvs, &normals))),
+                material_index: group.material,
+                ..Default::default()
+            })
+            .collect();
+        children.push(match nodes.len() {
+            1 => Node {
+                name: name.clone(),
+                ..nodes.remove(0)
+            },
+            _ => Node {
+                name: name.clone(),
+                children: nodes,
+                ..Default::default()
+            },
+        });
+    }
+    let mut defaults = Vec::new();
+    for library in libraries.iter() {
+        match loaded.get(library) {
+            Ok(bytes) => defaults.extend(materials_from_mtl(
+                library,
+                &String::from_utf8_lossy(bytes),
+            )?),
+            Err(e) => warn!("skipping material library {library} of {path}: {e}"),
+        }
+    }
+    let materials = materials
+        .into_iter()
+        .map(|name| match defaults.iter().find(|m| m.name() == name) {
+            Some(material) => material.pbr(),
+            None => PbrMaterial {
+                name,
+                ..Default::default()
+            },
+        })
+        .collect();
+    Ok(Scene {
+        name: path.into(),
+        children,
+        materials,
+    })
+}
+
+impl Group {
+    /// a mesh with a vertex per distinct corner, normals are computed if any corner lacks one
+    fn mesh(&self, positions: &[Vec3], uvs: &[Vec2], normals: &[Vec3]) -> TriMesh {
+        let with_uvs = self.corners.iter().all(|c| c.1.is_some());
+        let with_normals = self.corners.iter().all(|c| c.2.is_some());
+        let mut vertices: HashMap<Corner, u32> = HashMap::new();
+        let (mut p, mut t, mut n) = (Vec::new(), Vec::new(), Vec::new());
+        let mut indices = Vec::with_capacity(self.corners.len());
+        for corner in self.corners.iter() {
+            let index = *vertices.entry(*corner).or_insert_with(|| {
+                p.push(positions[corner.0]);
+                if let (true, Some(uv)) = (with_uvs, corner.1) {
+                    t.push(uvs[uv]);
+                }
+                if let (true, Some(normal)) = (with_normals, corner.2) {
+                    n.push(normals[normal]);
+                }
+                p.len() as u32 - 1
+            });
+            indices.push(index);
+        }
+        let mut mesh = TriMesh {
+            positions: Positions::F32(p),
+            indices: Indices::U32(indices),
+            uvs: with_uvs.then_some(t),
+            normals: with_normals.then_some(n),
+            ..Default::default()
+        };
+        if !with_normals {
+            mesh.compute_normals();
+        }
+        mesh
+    }
+}
+
+/// A material of an MTL file
+struct Phong {
+    name: String,
+    diffuse: [f32; 3],
+    specular: [f32; 3],
+    shininess: f32,
+    alpha: f32,
+    metallic: Option<f32>,
+    roughness: Option<f32>,
+}
+
+impl Phong {
+    fn new(name: String) -> Self {
+        Self {
+            name,
+            diffuse: [0.8; 3],
+            specular: [0.0; 3],
+            shininess: 0.0,
+            alpha: 1.0,
+            metallic: None,
+            roughness: None,
+        }
+    }
+
+    /// the closest physical material, see the module docs
+    fn material(&self) -> Material {
+        let mean = |c: [f32; 3]| c.iter().sum::<f32>() / 3.0;
+        let (diffuse, specular) = (mean(self.diffuse), mean(self.specular));
+        let metallic = self.metallic.unwrap_or_else(|| {
+            if specular > 0.0 {
+                (specular - diffuse) / specular
+            } else {
+                0.0
+            }
+        });
+        let metallic = metallic.clamp(0.0, 1.0);
+        // the Blinn-Phong exponent to a Beckmann roughness
+        let roughness = self
+            .roughness
+            .unwrap_or_else(|| (2.0 / (self.shininess.max(0.0) + 2.0)).sqrt());
+        // metals take their color from the specular reflection
+        let channel = |i: usize| {
+            let c = self.diffuse[i] * (1.0 - metallic) + self.specular[i] * metallic;
+            (c.clamp(0.0, 1.0) * 255.0).round() as u8
+        };
+        Material::new(
+            &self.name,
+            [channel(0), channel(1), channel(2)],
+            metallic,
+            roughness.clamp(0.0, 1.0),
+        )
+        .with_alpha(self.alpha.clamp(0.0, 1.0))
+    }
+}
+
+/// the materials of the MTL `text` read from `path`
+pub(crate) fn materials_from_mtl(path: &str, text: &str) -> Result<Vec<Material>, DefinitionError> {
+    let invalid = |line: usize| DefinitionError::InvalidObj {
+        path: path.into(),
+        line,
+    };
+    let mut materials: Vec<Phong> = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let mut words = line.split_whitespace();
+        let keyword = words.next();
+        if keyword == Some("newmtl") {
+            materials.push(Phong::new(rest(words)));
+            continue;
+        }
+        let Some(material) = materials.last_mut() else {
+            continue;
+        };
+        let mut value = || float(words.next()).ok_or_else(|| invalid(i + 1));
+        match keyword {
+            Some("Kd") => material.diffuse = color(&mut words).ok_or_else(|| invalid(i + 1))?,
+            Some("Ks") => material.specular = color(&mut words).ok_or_else(|| invalid(i + 1))?,
+            Some("Ns") => material.shininess = value()?,
+            Some("d") => material.alpha = value()?,
+            Some("Tr") => material.alpha = 1.0 - value()?,
+            Some("Pm") => material.metallic = Some(value()?),
+            Some("Pr") => material.roughness = Some(value()?),
+            _ => {}
+        }
+    }
+    Ok(materials.iter().map(Phong::material).collect())
+}
+
+/// the remaining words of a statement, e.g. a name with spaces
+fn rest<'a>(words: impl Iterator<Item = &'a str>) -> String {
+    words.collect::<Vec<_>>().join(" ")
+}
+
+/// a finite number, `nan` and `inf` are invalid
+fn float(word: Option<&str>) -> Option<f32> {
+    word?.parse().ok().filter(|value: &f32| value.is_finite())
+}
+
+fn vector<'a>(words: &mut impl Iterator<Item = &'a str>) -> Option<Vec3> {
+    Some(vec3(
+        float(words.next())?,
+        float(words.next())?,
+        float(words.next())?,
+    ))
+}
+
+/// an MTL color, a single value is used for all channels
+fn color<'a>(words: &mut impl Iterator<Item = &'a str>) -> Option<[f32; 3]> {
+    let r = float(words.next())?;
+    match words.next() {
+        None => Some([r; 3]),
+        g => Some([r, float(g)?, float(words.next())?]),
+    }
+}
+
+/// a face corner like `1`, `1/2`, `1//3` or `1/2/3`, `counts` are the elements read so far
+fn corner(word: &str, counts: [usize; 3]) -> Option<Corner> {
+    let mut indices = word.split('/');
+    let position = resolve(indices.next()?, counts[0])?;
+    let mut optional = |count: usize| match indices.next() {
+        None | Some("") => Some(None),
+        Some(index) => resolve(index, count).map(Some),
+    };
+    let uv = optional(counts[1])?;
+    let normal = optional(counts[2])?;
+    Some((position, uv, normal))
+}
+
+/// a 1-based or negative, relative OBJ index into `count` elements
+fn resolve(index: &str, count: usize) -> Option<usize> {
+    let index: isize = index.parse().ok()?;
+    let index = if index < 0 {
+        count as isize + index
+    } else {
+        index - 1
+    };
+    (0..count as isize)
+        .contains(&index)
+        .then_some(index as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scene(text: &str) -> Scene {
+        let mut loaded = RawAssets::new();
+        loaded.insert("model.obj", text.as_bytes().to_vec());
+        scene_from_obj("model.obj", &loaded).unwrap()
+    }
+
+    fn names(nodes: &[Node]) -> Vec<&str> {
+        nodes.iter().map(|node| node.name.as_str()).collect()
+    }
+
+    fn mesh(node: &Node) -> &TriMesh {
+        match node.geometry.as_ref() {
+            Some(Geometry::Triangles(mesh)) => mesh,
+            _ => panic!("{} has no mesh", node.name),
+        }
+    }
+
+    fn indices(node: &Node) -> Vec<u32> {
+        match &mesh(node).indices {
+            Indices::U32(indices) => indices.clone(),
+            _ => panic!("{} has no u32 indices", node.name),
+        }
+    }
+
+    const SQUARE: &str = "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\n";
+
+    #[test]
+    fn corners() {
+        let counts = [4, 2, 3];
+        assert_eq!(corner("1", counts), Some((0, None, None)));
+        assert_eq!(corner("2/1", counts), Some((1, Some(0), None)));
+        assert_eq!(corner("3//3", counts), Some((2, None, Some(2))));
+        assert_eq!(corner("4/2/1", counts), Some((3, Some(1), Some(0))));
+        assert_eq!(corner("-1/-2/-3", counts), Some((3, Some(0), Some(0))));
+        assert_eq!(corner("5", counts), None);
+        assert_eq!(corner("0", counts), None);
+        assert_eq!(corner("1/3", counts), None);
+        assert_eq!(corner("1//-4", counts), None);
+        assert_eq!(corner("a", counts), None);
+        assert_eq!(corner("", counts), None);
+    }
+
+    #[test]
+    fn indices_resolve() {
+        assert_eq!(resolve("1", 3), Some(0));
+        assert_eq!(resolve("3", 3), Some(2));
+        assert_eq!(resolve("-1", 3), Some(2));
+        assert_eq!(resolve("-3", 3), Some(0));
+        assert_eq!(resolve("4", 3), None);
+        assert_eq!(resolve("-4", 3), None);
+        assert_eq!(resolve("0", 3), None);
+        assert_eq!(resolve("1", 0), None);
+    }
+
+    #[test]
+    fn polygons_are_fanned() {
+        let scene = scene(&format!("{SQUARE}v 0.5 2 0\nf 1 2 3 4\nf 1 3 5 4 2\n"));
+        assert_eq!(names(&scene.children), ["default"]);
+        let node = &scene.children[0];
+        assert_eq!(indices(node), [0, 1, 2, 0, 2, 3, 0, 2, 4, 0, 4, 3, 0, 3, 1]);
+        assert_eq!(mesh(node).vertex_count(), 5);
+        assert!(mesh(node).normals.is_some());
+        assert_eq!(node.material_index, None);
+    }
+
+    #[test]
+    fn reappearing_groups_are_joined() {
+        let text = format!(
+            "{SQUARE}f 1 2 3\no Frame\nf 1 2 3\ng Arms\nf 1 2 3\ng\nf 2 3 4\n\
+             g Arms\nf 1 3 4\n"
+        );
+        let scene = scene(&text);
+        assert_eq!(names(&scene.children), ["default", "Frame", "Arms"]);
+        assert_eq!(indices(&scene.children[1]), [0, 1, 2, 1, 2, 3]);
+        assert_eq!(indices(&scene.children[2]), [0, 1, 2, 0, 2, 3]);
+        assert_eq!(mesh(&scene.children[2]).vertex_count(), 4);
+    }
+
+    #[test]
+    fn groups_are_split_per_material() {
+        let text = format!(
+            "{SQUARE}o Frame\nusemtl Steel\nf 1 2 3\ng Arms\nf 1 2 3\n\
+             usemtl Cloth\nf 1 3 4\nusemtl Steel\nf 2 3 4\n"
+        );
+        let scene = scene(&text);
+        assert_eq!(names(&scene.children), ["Frame", "Arms"]);
+        let (frame, arms) = (&scene.children[0], &scene.children[1]);
+        assert_eq!(frame.material_index, Some(0));
+        assert!(frame.children.is_empty());
+
+        assert!(arms.geometry.is_none());
+        assert_eq!(names(&arms.children), ["Arms (Steel)", "Arms (Cloth)"]);
+        assert_eq!(arms.children[0].material_index, Some(0));
+        assert_eq!(indices(&arms.children[0]), [0, 1, 2, 1, 2, 3]);
+        assert_eq!(arms.children[1].material_index, Some(1));
+        assert_eq!(indices(&arms.children[1]), [0, 1, 2]);
+
+        let materials: Vec<&str> = scene.materials.iter().map(|m| m.name.as_str()).collect();
+        assert_eq!(materials, ["Steel", "Cloth"]);
+    }
+
+    fn mtl(text: &str) -> Material {
+        let materials = materials_from_mtl("model.mtl", text).unwrap();
+        assert_eq!(materials.len(), 1);
+        materials.into_iter().next().unwrap()
+    }
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-4
+    }
+
+    #[test]
+    fn blender_materials() {
+        // as written by Blender without the PBR extensions
+        let material = mtl(
+            "newmtl Plastic\nNs 250.000000\nKa 1.0 1.0 1.0\nKd 0.8 0.8 0.8\n\
+             Ks 0.5 0.5 0.5\nKe 0.0 0.0 0.0\nNi 1.45\nd 1.0\nillum 2\n",
+        );
+        assert_eq!(material.name(), "Plastic");
+        assert_eq!(material.rgb(), [204, 204, 204]);
+        assert_eq!(material.metallic(), 0.0);
+        assert!(close(material.roughness(), (2.0f32 / 252.0).sqrt()));
+        assert_eq!(material.alpha(), 1.0);
+    }
+
+    #[test]
+    fn bright_speculars_are_metallic() {
+        let material = mtl("newmtl Brass\nKd 0.1 0.1 0.1\nKs 0.9 0.6 0.3\nNs 0\n");
+        assert!(close(material.metallic(), (0.6 - 0.1) / 0.6));
+        assert_eq!(material.roughness(), 1.0);
+    }
+
+    #[test]
+    fn pbr_extensions_come_first() {
+        let material = mtl("newmtl Steel\nKd 0.2 0.2 0.2\nKs 1 1 1\nNs 900\nPm 0.25\nPr 0.6\n");
+        assert_eq!(material.metallic(), 0.25);
+        assert_eq!(material.roughness(), 0.6);
+        // the order of the statements does not matter
+        let material = mtl("newmtl Steel\nPm 0.25\nPr 0.6\nKd 0.2 0.2 0.2\nKs 1 1 1\nNs 900\n");
+        assert_eq!(material.metallic(), 0.25);
+        assert_eq!(material.roughness(), 0.6);
+    }
+
+    #[test]
+    fn transparency() {
+        assert_eq!(mtl("newmtl Glass\nd 0.5\n").alpha(), 0.5);
+        assert_eq!(mtl("newmtl Glass\nTr 0.25\n").alpha(), 0.75);
+        assert_eq!(mtl("newmtl Glass\nd 1.5\n").alpha(), 1.0);
+        assert!(materials_from_mtl("model.mtl", "newmtl Glass\nd nan\n").is_err());
+        assert!(materials_from_mtl("model.mtl", "newmtl Glass\nTr NaN\n").is_err());
+        assert!(materials_from_mtl("model.mtl", "newmtl Glass\nNs inf\n").is_err());
+    }
+
+    #[test]
+    fn single_value_colors() {
+        let material = mtl("newmtl Grey\nKd 0.5\n");
+        assert_eq!(material.rgb(), [128, 128, 128]);
+        assert!(materials_from_mtl("model.mtl", "newmtl Grey\nKd\n").is_err());
+        assert!(materials_from_mtl("model.mtl", "newmtl Grey\nKd 0.5 0.25\n").is_err());
+        assert!(materials_from_mtl("model.mtl", "newmtl Grey\nKd 0.5 x 1\n").is_err());
+        assert!(materials_from_mtl("model.mtl", "newmtl Grey\nKd nan\n").is_err());
+        assert!(materials_from_mtl("model.mtl", "newmtl Grey\nKs 0.5 nan 0.5\n").is_err());
+        assert!(materials_from_mtl("model.mtl", "newmtl Grey\nPm -inf\n").is_err());
+    }
+
+    #[test]
+    fn invalid_faces() {
+        let mut loaded = RawAssets::new();
+        loaded.insert("model.obj", format!("{SQUARE}f 1 2\n").into_bytes());
+        assert!(scene_from_obj("model.obj", &loaded).is_err());
+        loaded.insert("model.obj", format!("{SQUARE}\nf 1 2 5\n").into_bytes());
+        assert!(scene_from_obj("model.obj", &loaded).is_err());
+    }
+}
@@ -0,0 +1,354 @@
+//! Continuous numeric parameters, e.g. a seat height or a table width.
+//!
+//! A parameter is a value between `min` and `max` in multiples of `step`, shown as a slider.
+//! Parts and assemblies follow it with [drives](Drive) that move or stretch them by how far
+//! the value is from its default, so meshes are modeled at the default size.
+//!
+//! ```toml
+//! [[parameters]]
+//! id = "width"
+//! label = "Width"
+//! min = 1200.0
+//! max = 2000.0
+//! step = 10.0
+//! default = 1600.0
+//! unit = "mm"
+//! price = 0.05
+//!
+//! [[assys.parts]]
+//! name = "Table Top"
+//! mesh = "top.obj"
+//! material = "wood"
+//! drives = [{ parameter = "width", stretch = "x" }]
+//!
+//! [[assys.parts]]
+//! name = "Right Legs"
+//! mesh = "legs.obj"
+//! material = "metals"
+//! drives = [{ parameter = "width", translate = [0.5, 0.0, 0.0] }]
+//! ```
+//!
+//! Stretching scales the whole mesh, which distorts legs and rounded edges. A `region` drive
+//! instead moves the vertices past a cut plane and leaves the rest as is, so only the faces
+//! crossing the cut get longer. A table top cut on both sides keeps its corner profiles:
+//!
+//! ```toml
+//! drives = [
+//!     { parameter = "width", region = { axis = "x", cut = 500.0, per_unit = 0.5 } },
+//!     { parameter = "width", region = { axis = "x", cut = -500.0, per_unit = -0.5 } },
+//! ]
+//! ```
+use std::{borrow::Cow, cell::RefCell, fmt::Display, rc::Rc};
+
+use serde::{Deserialize, Serialize};
+use three_d_asset::{AxisAlignedBoundingBox as AABB, Positions, TriMesh};
+
+use super::{price::Price, transform::Transform};
+
+pub type SharedParameter = Rc<RefCell<Parameter>>;
+
+/// A number the customer picks with a slider
+#[derive(Debug, Clone, PartialEq)]
+pub struct Parameter {
+    label: Box<str>,
+    unit: Box<str>,
+    min: f32,
+    max: f32,
+    /// the value changes in multiples of this, any value if zero
+    step: f32,
+    default: f32,
+    value: f32,
+    /// add-on price per unit above `min`
+    price: Price,
+}
+
+impl Parameter {
+    /// `default` is clamped to `min..=max`, panics if `min > max` or either is NaN
+    pub fn new(label: impl Into<Box<str>>, min: f32, max: f32, default: f32) -> Self {
+        let default = default.clamp(min, max);
+        Self {
+            label: label.into(),
+            unit: "".into(),
+            min,
+            max,
+            step: 0.0,
+            default,
+            value: default,
+            price: Price::ZERO,
+        }
+    }
+    pub fn with_step(mut self, step: f32) -> Self {
+        self.step = step.max(0.0);
+        self
+    }
+    pub fn with_unit(mut self, unit: impl Into<Box<str>>) -> Self {
+        self.unit = unit.into();
+        self
+    }
+    /// sets the add-on price per unit above `min`
+    pub fn with_price(mut self, per_unit: Price) -> Self {
+        self.price = per_unit;
+        self
+    }
+    pub fn shared(self) -> SharedParameter {
+        Rc::new(RefCell::new(self))
+    }
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+    pub fn unit(&self) -> &str {
+        &self.unit
+    }
+    pub fn min(&self) -> f32 {
+        self.min
+    }
+    pub fn max(&self) -> f32 {
+        self.max
+    }
+    pub fn step(&self) -> f32 {
+        self.step
+    }
+    pub fn default(&self) -> f32 {
+        self.default
+    }
+    pub fn value(&self) -> f32 {
+        self.value
+    }
+
+    /// whether `value` is in range and a multiple of `step` away from `min`
+    pub fn allows(&self, value: f32) -> bool {
+        // steps like 0.1 are not exact in binary
+        let on_step = (self.snap(value) - value).abs() <= self.step * 1e-3;
+        value.is_finite() && (self.min..=self.max).contains(&value) && on_step
+    }
+
+    /// the closest allowed value to `value`
+    pub fn snap(&self, value: f32) -> f32 {
+        let value = value.clamp(self.min, self.max);
+        if self.step > 0.0 {
+            let steps = ((value - self.min) / self.step).round();
+            (self.min + steps * self.step).min(self.max)
+        } else {
+            value
+        }
+    }
+
+    /// sets the closest allowed value to `value`, returns false if it is not a number
+    pub fn set(&mut self, value: f32) -> bool {
+        if value.is_nan() {
+            return false;
+        }
+        self.value = self.snap(value);
+        true
+    }
+
+    /// how far the value is from the default
+    pub fn offset(&self) -> f32 {
+        self.value - self.default
+    }
+
+    /// the add-on price of the current value
+    pub fn price(&self) -> Price {
+        Price::from_decimal(self.price.as_decimal() * (self.value - self.min) as f64)
+    }
+}
+
+impl Display for Parameter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.unit.is_empty() {
+            write!(f, "{} {}", self.label, self.value)
+        } else {
+            write!(f, "{} {} {}", self.label, self.value, self.unit)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Axis {
+    X,
+    Y,
+    Z,
+}
+
+impl Axis {
+    pub fn index(&self) -> usize {
+        match self {
+            Axis::X => 0,
+            Axis::Y => 1,
+            Axis::Z => 2,
+        }
+    }
+}
+
+/// How a parameter changes a part or assembly
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Effect {
+    /// moves by this much per unit the value is above its default
+    Translate([f32; 3]),
+    /// scales the mesh along the axis by the ratio of the value to its default,
+    /// so the parameter has to stay above 0
+    Stretch(Axis),
+    /// moves the vertices of a region of the mesh
+    Region(Region),
+}
+
+impl Effect {
+    /// whether the effect changes the size of a part rather than only its place
+    pub fn is_resize(&self) -> bool {
+        !matches!(self, Effect::Translate(_))
+    }
+}
+
+/// The vertices of a mesh past a cut plane, moved together to stretch the faces crossing it
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Region {
+    pub axis: Axis,
+    /// position of the cut plane along `axis`, in mesh space
+    pub cut: f32,
+    /// distance moved per unit the value is above its default,
+    /// its sign picks the side of the cut that moves
+    pub per_unit: f32,
+}
+
+impl Region {
+    pub fn contains(&self, coordinate: f32) -> bool {
+        if self.per_unit < 0.0 {
+            coordinate < self.cut
+        } else {
+            coordinate > self.cut
+        }
+    }
+
+    /// whether the cut lies inside `bb` and further from both of its ends than the region
+    /// moves towards it at the lowest value of `parameter`
+    pub fn fits(&self, bb: &AABB, parameter: &Parameter) -> bool {
+        let i = self.axis.index();
+        let shrink = ((parameter.min() - parameter.default()) * self.per_unit).abs();
+        let space = (self.cut - bb.min()[i]).min(bb.max()[i] - self.cut);
+        space > 0.0 && shrink < space
+    }
+
+    /// moves the vertices of the region by `per_unit * offset` along the axis,
+    /// the cut should be further from the ends of the mesh than it shrinks, see [`Self::fits`]
+    pub fn stretch(&self, mesh: &mut TriMesh, offset: f32) {
+        let i = self.axis.index();
+        let distance = self.per_unit * offset;
+        match &mut mesh.positions {
+            Positions::F32(v) => {
+                for p in v.iter_mut().filter(|p| self.contains(p[i])) {
+                    p[i] += distance;
+                }
+            }
+            Positions::F64(v) => {
+                for p in v.iter_mut().filter(|p| self.contains(p[i] as f32)) {
+                    p[i] += distance as f64;
+                }
+            }
+        }
+    }
+}
+
+/// A parameter moving or stretching a part or assembly
+#[derive(Debug, Clone, PartialEq)]
+pub struct Drive {
+    parameter: SharedParameter,
+    effect: Effect,
+}
+
+impl Drive {
+    pub fn new(parameter: &SharedParameter, effect: Effect) -> Self {
+        Self {
+            parameter: parameter.clone(),
+            effect,
+        }
+    }
+    pub fn parameter(&self) -> &SharedParameter {
+        &self.parameter
+    }
+    pub fn effect(&self) -> &Effect {
+        &self.effect
+    }
+
+    /// `transform` changed by the current value of the parameter
+    pub fn apply(&self, mut transform: Transform) -> Transform {
+        let parameter = self.parameter.borrow();
+        match self.effect {
+            Effect::Translate(per_unit) => {
+                for (t, d) in transform.translation.iter_mut().zip(per_unit) {
+                    *t += d * parameter.offset();
+                }
+            }
+            Effect::Stretch(axis) => {
+                if parameter.default() != 0.0 {
+                    transform.scale[axis.index()] *= parameter.value() / parameter.default();
+                }
+            }
+            // regions change the mesh, not its placement
+            Effect::Region(_) => {}
+        }
+        transform
+    }
+}
+
+/// `transform` changed by all `drives`
+pub(crate) fn driven(transform: Transform, drives: &[Drive]) -> Transform {
+    drives.iter().fold(transform, |t, drive| drive.apply(t))
+}
+
+/// the offsets of the parameters of the region drives, the mesh has to be
+/// deformed again whenever they change
+pub(crate) fn deformation(drives: &[Drive]) -> Vec<f32> {
+    let regions = drives
+        .iter()
+        .filter(|d| matches!(d.effect, Effect::Region(_)));
+    regions.map(|d| d.parameter.borrow().offset()).collect()
+}
+
+/// `shape` with all region drives applied, borrowed if there are none
+pub(crate) fn deformed<'a>(shape: &'a TriMesh, drives: &[Drive]) -> Cow<'a, TriMesh> {
+    let mut shape = Cow::Borrowed(shape);
+    for drive in drives.iter() {
+        if let Effect::Region(region) = drive.effect {
+            let offset = drive.parameter.borrow().offset();
+            if offset != 0.0 {
+                region.stretch(shape.to_mut(), offset);
+            }
+        }
+    }
+    shape
+}
+
+/// `bb` of a shape moved like [`deformed`] moves the shape, as long as the regions fit it
+pub(crate) fn deformed_aabb(bb: AABB, drives: &[Drive]) -> AABB {
+    if bb.is_empty() {
+        return bb;
+    }
+    let (mut min, mut max) = (bb.min(), bb.max());
+    for drive in drives.iter() {
+        if let Effect::Region(region) = drive.effect {
+            let i = region.axis.index();
+            let distance = region.per_unit * drive.parameter.borrow().offset();
+            // the region holds the end of the mesh on its side of the cut
+            if region.per_unit < 0.0 {
+                min[i] += distance;
+            } else {
+                max[i] += distance;
+            }
+        }
+    }
+    AABB::new_with_positions(&[min, max])
+}
+
+/// the values of the parameters resizing a part, e.g. `Width 1600 mm`
+pub(crate) fn sizes<'a>(drives: impl IntoIterator<Item = &'a Drive>) -> String {
+    let mut sizes: Vec<String> = Vec::new();
+    for drive in drives.into_iter().filter(|d| d.effect.is_resize()) {
+        let size = drive.parameter.borrow().to_string();
+        if !sizes.contains(&size) {
+            sizes.push(size);
+        }
+    }
+    sizes.join(", ")
+}
@@ -1,40 +1,80 @@
-use log::{info, warn};
+use log::info;
+#[cfg(feature = "render")]
+use log::warn;
 
+#[cfg(feature = "render")]
 use three_d::{
     egui::{InnerResponse, Ui},
-    Context, Gm, Mesh, Object, PhysicalMaterial,
+    Context, Object,
 };
-use three_d_asset::TriMesh;
+use three_d_asset::{AxisAlignedBoundingBox as AABB, TriMesh};
 
 use super::{
     material::{Material, MaterialCollection},
+    price::Price,
     shape::cube,
-    PbrModel,
+    transform::Transform,
 };
+#[cfg(feature = "render")]
+use super::{
+    rules::{Blocked, Condition},
+    Model,
+};
+use crate::Error;
 
 pub struct Part {
     name: Box<str>,
     shape: TriMesh,
+    transform: Transform,
     // current_material: usize,
     // materials: Box<[Material]>,
     material: MaterialCollection,
     optional: bool,
     opt_in: bool,
-    model: Option<PbrModel>,
+    price: Price,
+    part_number: Box<str>,
+    #[cfg(feature = "render")]
+    model: Option<Model>,
 }
 impl Part {
-    fn new(name: Box<str>, shape: TriMesh, material: MaterialCollection, optional: bool) -> Self {
-        Self {
+    /// fails if `material` has nothing to pick from
+    pub fn new(
+        name: Box<str>,
+        shape: TriMesh,
+        material: MaterialCollection,
+        optional: bool,
+    ) -> crate::Result<Self> {
+        if material.len() == 0 {
+            return Err(Error::NoMaterials(name));
+        }
+        Ok(Self {
             name,
             shape,
+            transform: Transform::IDENTITY,
             material,
             optional,
             opt_in: true,
+            price: Price::ZERO,
+            part_number: "".into(),
+            #[cfg(feature = "render")]
             model: None,
-        }
+        })
     }
 
-    pub async fn placeholder_chair() -> Box<[Self]> {
+    pub fn with_price(mut self, price: Price) -> Self {
+        self.price = price;
+        self
+    }
+    pub fn with_part_number(mut self, part_number: impl Into<Box<str>>) -> Self {
+        self.part_number = part_number.into();
+        self
+    }
+    pub fn with_transform(mut self, transform: Transform) -> Self {
+        self.transform = transform;
+        self
+    }
+
+    pub async fn placeholder_chair() -> crate::Result<Box<[Self]>> {
         let asset_info = [
             (
                 "chair/skeleton.obj",
@@ -76,22 +116,13 @@ impl Part {
             .map(|row| format!("./assets/{}", row.0))
             .collect();
 
-        let mut loaded = match three_d_asset::io::load_async(&paths).await {
-            Ok(loaded) => {
-                info!("loaded skybox from assets");
-                loaded
-            }
-            Err(e) => {
-                // log::error!("error: {}", e);
-                println!("error: {}", e);
-                panic!("failed to download the necessary assets, to enable running this example offline, place the relevant assets in a folder called 'assets' next to the three-d source")
-            }
-        };
+        let mut loaded = three_d_asset::io::load_async(&paths).await?;
+        info!("loaded chair from assets");
 
         asset_info
             .into_iter()
             .map(|(path, name, materials, optional)| {
-                let shape = loaded.deserialize(path).expect("failed to deserialize");
+                let shape = loaded.deserialize(path)?;
                 Self::new(name.into(), shape, materials, optional)
             })
             .collect()
@@ -101,13 +132,17 @@ impl Part {
         Self {
             name: "Sphere".into(),
             shape: TriMesh::sphere(32),
+            transform: Transform::IDENTITY,
             material: MaterialCollection::metals(),
             optional: true,
             opt_in: true,
+            price: Price::ZERO,
+            part_number: "".into(),
+            #[cfg(feature = "render")]
             model: None,
         }
     }
-    pub fn placeholder2() -> Self {
+    pub fn placeholder2() -> crate::Result<Self> {
         let shape = cube(0.0, -2.0, 0.);
         Self::new("Cube".into(), shape, MaterialCollection::metals(), false)
     }
@@ -117,67 +152,127 @@ impl Part {
     pub fn shape(&self) -> &TriMesh {
         &self.shape
     }
+    pub fn transform(&self) -> &Transform {
+        &self.transform
+    }
+    /// places self, the gpu model follows right away
+    pub fn set_transform(&mut self, transform: Transform) {
+        self.transform = transform;
+        #[cfg(feature = "render")]
+        if let Some(model) = self.model.as_mut() {
+            model.set_transformation(transform.matrix());
+        }
+    }
+    /// the box around the transformed shape
+    pub fn bounding_box(&self) -> AABB {
+        self.transform.bounding_box(self.shape.compute_aabb())
+    }
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    pub fn price(&self) -> Price {
+        self.price
+    }
+    pub fn part_number(&self) -> &str {
+        &self.part_number
+    }
+    pub fn is_optional(&self) -> bool {
+        self.optional
+    }
+    pub fn opt_in(&self) -> bool {
+        self.opt_in
+    }
+    /// only optional parts can be opted out
+    pub fn set_opt_in(&mut self, opt_in: bool) {
+        self.opt_in = opt_in || !self.optional;
+    }
+    pub fn materials(&self) -> &MaterialCollection {
+        &self.material
+    }
+    pub fn materials_mut(&mut self) -> &mut MaterialCollection {
+        &mut self.material
+    }
+}
+
+#[cfg(feature = "render")]
+impl Part {
     pub fn init(&mut self, ctx: &Context) {
-        let material = PhysicalMaterial::new_opaque(ctx, &self.material().pbr());
-        let mesh = Mesh::new(ctx, &self.shape);
-        let model = Gm::new(mesh, material);
+        let mut model = Model::new(ctx, &self.shape, self.material.current());
+        model.set_transformation(self.transform.matrix());
         self.model = Some(model);
     }
     pub fn update(&mut self) {
-        let rgb = self.material().rgb().into();
-        let metallic = self.material().metallic();
-        let roughness = self.material().roughness();
-        let model = self.model.as_mut();
-        match model {
+        match self.model.as_mut() {
             Some(model) => {
-                model.material.albedo = rgb;
-                model.material.metallic = metallic;
-                model.material.roughness = roughness;
+                model.set_material(self.material.current());
+                // a material with a normal map needs tangents the mesh may not have yet
+                if model.lacks_tangents() {
+                    model.set_shape(&self.shape);
+                    model.set_transformation(self.transform.matrix());
+                }
             }
             None => warn!("model has not been initated, doing nothing here!"),
         }
     }
 
-    pub fn object(&self) -> Option<&dyn Object> {
-        if self.opt_in {
-            Some(self.model.as_ref().expect("model has not been initated"))
-        } else {
-            None
+    /// `None` if self is opted out, fails if self is not initated
+    pub fn object(&self) -> crate::Result<Option<&dyn Object>> {
+        if !self.opt_in {
+            return Ok(None);
+        }
+        match self.model.as_ref() {
+            Some(model) => Ok(Some(model.object())),
+            None => Err(Error::Uninitialized(self.name.clone())),
         }
     }
 }
 
+#[cfg(feature = "render")]
 impl Part {
-    pub fn add_controls(&mut self, ui: &mut Ui) {
+    pub fn add_controls(&mut self, ui: &mut Ui, blocked: &Blocked) {
         let options = (self.optional, self.material.len());
 
         match options {
-            (_, 0) => panic!("Componets must have atleast one material"),
-            (false, 1) => (), // self is not configurable
-            (true, 1) => self.show_toggle(ui),
+            // self is not configurable, `Part::new` rejects parts without materials
+            (_, 0) | (false, 1) => (),
+            (true, 1) => self.show_toggle(ui, blocked),
             (true, _) => {
-                self.show_toggle(ui);
-                ui.add_enabled_ui(self.opt_in, |ui| self.material_group(ui));
+                self.show_toggle(ui, blocked);
+                ui.add_enabled_ui(self.opt_in, |ui| self.material_group(ui, blocked));
             }
             (false, _) => {
                 ui.label(self.name.as_ref());
-                self.material_group(ui);
+                self.material_group(ui, blocked);
             }
         }
     }
 
-    fn material_group(&mut self, ui: &mut Ui) -> InnerResponse<()> {
+    fn material_group(&mut self, ui: &mut Ui, blocked: &Blocked) -> InnerResponse<()> {
         ui.group(|ui| {
-            self.material_picker(ui);
+            self.material_picker(ui, blocked);
         })
     }
-    fn material_picker(&mut self, ui: &mut Ui) {
-        for i in 0..self.material.len() {
-            let text = self.material.options()[i].to_string();
-            ui.radio_value(&mut self.material.current_material, i, text);
-        }
+    fn material_picker(&mut self, ui: &mut Ui, blocked: &Blocked) {
+        self.material.add_swatches(ui, blocked);
+    }
+    fn show_toggle(&mut self, ui: &mut Ui, blocked: &Blocked) {
+        let condition = Condition::option(&self.name);
+        blocked.checkbox(ui, &mut self.opt_in, &condition, &self.name);
     }
-    fn show_toggle(&mut self, ui: &mut Ui) {
-        ui.checkbox(&mut self.opt_in, self.name.as_ref());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parts_need_materials() {
+        let empty = MaterialCollection::new("Empty".into(), [].into());
+        match Part::new("Seat".into(), cube(0.0, 0.0, 0.0), empty, false) {
+            Err(Error::NoMaterials(part)) => assert_eq!(&*part, "Seat"),
+            _ => panic!("a part without materials was accepted"),
+        }
+        let metals = MaterialCollection::metals();
+        assert!(Part::new("Seat".into(), cube(0.0, 0.0, 0.0), metals, false).is_ok());
     }
 }
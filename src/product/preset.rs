@@ -0,0 +1,102 @@
+//! Named starting points for a configuration, e.g. "Basic" or "Showroom".
+//!
+//! ```toml
+//! [[presets]]
+//! name = "Basic"
+//! options = { arms = false }
+//! materials = { metals = "aluminium", fabrics = "pink-fabric" }
+//! parameters = { seat_height = 450.0 }
+//! ```
+//!
+//! Everything a preset does not mention keeps its default.
+#[cfg(feature = "render")]
+use log::warn;
+#[cfg(feature = "render")]
+use three_d::egui::{ComboBox, Ui};
+
+use super::{configuration::ConfigurationError, Configuration, Product};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Preset {
+    name: Box<str>,
+    config: Configuration,
+}
+
+impl Preset {
+    pub fn new(name: &str, config: Configuration) -> Self {
+        Self {
+            name: name.into(),
+            config,
+        }
+    }
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    pub fn configuration(&self) -> &Configuration {
+        &self.config
+    }
+}
+
+impl Product {
+    pub fn presets(&self) -> &[Preset] {
+        &self.presets
+    }
+
+    /// the last applied preset and whether the configuration still matches it
+    pub fn preset(&self) -> Option<(&Preset, bool)> {
+        let preset = self.presets.get(self.preset?)?;
+        Some((preset, preset.config == self.configuration()))
+    }
+
+    /// applies the preset at `index`, nothing is changed if it does not fit
+    pub fn apply_preset(&mut self, index: usize) -> Result<(), ConfigurationError> {
+        let preset = self
+            .presets
+            .get(index)
+            .ok_or(ConfigurationError::Mismatch)?;
+        let config = preset.config.clone();
+        self.configure(&config)?;
+        self.preset = Some(index);
+        Ok(())
+    }
+
+    /// sets the presets, the first one matching the current configuration is selected
+    pub(crate) fn set_presets(&mut self, presets: Box<[Preset]>) {
+        let config = self.configuration();
+        self.preset = presets.iter().position(|p| p.config == config);
+        self.presets = presets;
+    }
+}
+
+#[cfg(feature = "render")]
+impl Product {
+    /// a dropdown of the presets, marked as modified once the user diverged
+    pub fn add_preset_ui(&mut self, ui: &mut Ui) {
+        if self.presets.is_empty() {
+            return;
+        }
+        let text = match self.preset() {
+            Some((preset, true)) => preset.name().to_string(),
+            Some((preset, false)) => format!("{} (modified)", preset.name()),
+            None => "Custom".to_string(),
+        };
+        let mut chosen = None;
+        ComboBox::from_label("Preset")
+            .selected_text(text)
+            .show_ui(ui, |ui| {
+                for (i, preset) in self.presets.iter().enumerate() {
+                    if ui
+                        .selectable_label(self.preset == Some(i), preset.name())
+                        .clicked()
+                    {
+                        chosen = Some(i);
+                    }
+                }
+            });
+        if let Some(index) = chosen {
+            if let Err(e) = self.apply_preset(index) {
+                warn!("failed to apply preset: {}", e);
+            }
+        }
+    }
+}
@@ -0,0 +1,302 @@
+//! Prices and the itemised quote of a configuration.
+use std::fmt::Display;
+use std::iter::Sum;
+use std::ops::{Add, AddAssign, Mul};
+use std::rc::Rc;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+#[cfg(feature = "render")]
+use three_d::egui::{Grid, Ui};
+
+use super::{parameter::SharedParameter, Include, Product};
+
+/// An amount of money in cents, written as a decimal number in definitions
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Price(i64);
+
+impl Price {
+    pub const ZERO: Price = Price(0);
+
+    pub fn from_cents(cents: i64) -> Self {
+        Self(cents)
+    }
+    pub fn from_decimal(amount: f64) -> Self {
+        Self((amount * 100.0).round() as i64)
+    }
+    pub fn cents(&self) -> i64 {
+        self.0
+    }
+    pub fn as_decimal(&self) -> f64 {
+        self.0 as f64 / 100.0
+    }
+    pub fn is_zero(&self) -> bool {
+        self.0 == 0
+    }
+}
+
+impl Display for Price {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let sign = if self.0 < 0 { "-" } else { "" };
+        let cents = self.0.unsigned_abs();
+        write!(f, "{}{}.{:02}", sign, cents / 100, cents % 100)
+    }
+}
+
+impl Add for Price {
+    type Output = Price;
+    fn add(self, rhs: Self) -> Self::Output {
+        Price(self.0 + rhs.0)
+    }
+}
+impl AddAssign for Price {
+    fn add_assign(&mut self, rhs: Self) {
+        self.0 += rhs.0;
+    }
+}
+impl Mul<usize> for Price {
+    type Output = Price;
+    fn mul(self, count: usize) -> Self::Output {
+        Price(self.0 * count as i64)
+    }
+}
+impl Sum for Price {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(Price::ZERO, |a, b| a + b)
+    }
+}
+
+impl Serialize for Price {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_f64(self.as_decimal())
+    }
+}
+impl<'de> Deserialize<'de> for Price {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let amount = f64::deserialize(deserializer)?;
+        if !amount.is_finite() {
+            return Err(serde::de::Error::custom("price must be a finite number"));
+        }
+        Ok(Self::from_decimal(amount))
+    }
+}
+
+/// One row of a quote
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PriceLine {
+    pub label: Box<str>,
+    pub amount: Price,
+}
+
+/// The itemised price of the current configuration
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct Quote {
+    pub currency: Box<str>,
+    pub lines: Vec<PriceLine>,
+}
+
+impl Quote {
+    fn push(&mut self, label: impl Into<Box<str>>, amount: Price) {
+        if !amount.is_zero() {
+            self.lines.push(PriceLine {
+                label: label.into(),
+                amount,
+            });
+        }
+    }
+
+    pub fn total(&self) -> Price {
+        self.lines.iter().map(|line| line.amount).sum()
+    }
+}
+
+impl Product {
+    pub fn currency(&self) -> &str {
+        &self.currency
+    }
+
+    /// prices every included part, its material, the enabled add-ons and the parameters
+    pub fn quote(&self) -> Quote {
+        let mut quote = Quote {
+            currency: self.currency.clone(),
+            lines: Vec::new(),
+        };
+        for part in self.parts.iter().filter(|p| p.opt_in()) {
+            let material = part.material();
+            quote.push(part.name(), part.price());
+            quote.push(format!("{}: {}", part.name(), material), material.price());
+        }
+        for assy in self.assys.iter() {
+            for part in assy.shown_parts() {
+                let material = part.material().borrow();
+                let material = material.current();
+                // instanced parts are priced per copy
+                let count = part.body().quantity();
+                let name = match count {
+                    1 => part.name().to_string(),
+                    n => format!("{} x{n}", part.name()),
+                };
+                quote.push(name.as_str(), part.price() * count);
+                quote.push(format!("{name}: {material}"), material.price() * count);
+            }
+        }
+        // an option shared by several assemblies is priced once
+        let mut toggles: Vec<&Include> = Vec::new();
+        for toggle in self.assys.iter().flat_map(|a| a.shown_toggles()) {
+            if !toggles.contains(&toggle) {
+                toggles.push(toggle);
+            }
+        }
+        for toggle in toggles.into_iter().filter(|inc| inc.is_show()) {
+            if let Some(label) = toggle.label() {
+                quote.push(label, toggle.price());
+            }
+        }
+        // assemblies can share a parameter, it is priced once if it drives any shown part
+        let mut parameters: Vec<&SharedParameter> = Vec::new();
+        for parameter in self.assys.iter().flat_map(|a| a.parameters().iter()) {
+            if !parameters.iter().any(|p| Rc::ptr_eq(p, parameter)) {
+                parameters.push(parameter);
+            }
+        }
+        for parameter in parameters {
+            if self.assys.iter().any(|a| a.drives_shown(parameter)) {
+                let parameter = parameter.borrow();
+                quote.push(parameter.to_string(), parameter.price());
+            }
+        }
+        quote
+    }
+}
+
+#[cfg(feature = "render")]
+impl Quote {
+    pub fn add_ui(&self, ui: &mut Ui) {
+        ui.heading("Price");
+        Grid::new("quote")
+            .num_columns(2)
+            .striped(true)
+            .show(ui, |ui| {
+                for line in self.lines.iter() {
+                    ui.label(line.label.as_ref());
+                    ui.label(format!("{} {}", line.amount, self.currency));
+                    ui.end_row();
+                }
+                ui.strong("Total");
+                ui.strong(format!("{} {}", self.total(), self.currency));
+                ui.end_row();
+            });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::product::{
+        material::MaterialCollection,
+        parameter::{Drive, Effect, Parameter},
+        shape::cube,
+        Assy, Body, ConfigPart, Include,
+    };
+
+    /// an assembly with a single part driven by `height`
+    fn driven(name: &str, height: &SharedParameter, include: Include) -> Assy {
+        let drives = [Drive::new(height, Effect::Translate([0.0, 1.0, 0.0]))];
+        let body = Body::from(cube(0.0, 0.0, 0.0)).with_drives(drives.into());
+        let material = MaterialCollection::metals().into();
+        let part = ConfigPart::new(name.into(), body, material, include);
+        Assy::new([part].into()).unwrap()
+    }
+
+    fn parameter_lines(product: &Product) -> Vec<PriceLine> {
+        let quote = product.quote();
+        let lines = quote.lines.into_iter();
+        lines.filter(|l| l.label.starts_with("Height")).collect()
+    }
+
+    fn option_lines(product: &Product) -> Vec<PriceLine> {
+        let quote = product.quote();
+        let lines = quote.lines.into_iter();
+        lines.filter(|l| l.label.starts_with("Arms")).collect()
+    }
+
+    fn plain(name: &str, include: Include) -> ConfigPart {
+        let material = MaterialCollection::metals().into();
+        ConfigPart::new(name.into(), cube(0.0, 0.0, 0.0).into(), material, include)
+    }
+
+    #[test]
+    fn shared_options_are_priced_once() {
+        let arms = Include::optinal("Arms", true).with_price(Price::from_cents(4500));
+        let assys = [
+            Assy::new([plain("Left Arm", arms.clone())].into()).unwrap(),
+            Assy::new([plain("Right Arm", arms.clone())].into()).unwrap(),
+        ];
+        let product = Product::new("Chair", [].into(), assys.into());
+        let lines = option_lines(&product);
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0].amount, Price::from_cents(4500));
+
+        arms.set(false);
+        assert!(option_lines(&product).is_empty());
+    }
+
+    #[test]
+    fn options_of_hidden_assemblies_are_free() {
+        let arms = Include::optinal("Arms", true).with_price(Price::from_cents(4500));
+        let frame = Include::optinal("Frame", false);
+        let child = Assy::new([plain("Arm", arms.clone())].into()).unwrap();
+        let with_child = Assy::nested([plain("Seat", Include::MustHave)].into(), [child].into())
+            .unwrap()
+            .with_include(frame.clone());
+        let hidden = Assy::new([plain("Other Arm", arms)].into())
+            .unwrap()
+            .with_include(Include::optinal("Hidden", false));
+        let product = Product::new("Chair", [].into(), [with_child, hidden].into());
+        assert!(option_lines(&product).is_empty());
+
+        frame.set(true);
+        assert_eq!(option_lines(&product).len(), 1);
+    }
+
+    #[test]
+    fn shared_parameters_are_priced_once() {
+        let height = Parameter::new("Height", 400.0, 600.0, 450.0)
+            .with_price(Price::from_decimal(0.1))
+            .shared();
+        let assys = [
+            driven("Left", &height, Include::MustHave),
+            driven("Right", &height, Include::MustHave),
+        ];
+        let product = Product::new("Table", [].into(), assys.into());
+        let lines = parameter_lines(&product);
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0].label.as_ref(), "Height 450");
+        assert_eq!(lines[0].amount, Price::from_cents(500));
+    }
+
+    #[test]
+    fn hidden_parameters_are_free() {
+        let height = Parameter::new("Height", 400.0, 600.0, 450.0)
+            .with_price(Price::from_decimal(0.1))
+            .shared();
+        let extension = Include::optinal("Extension", false);
+        let hidden = Assy::new(
+            [ConfigPart::new(
+                "Frame".into(),
+                cube(0.0, 0.0, 0.0).into(),
+                MaterialCollection::metals().into(),
+                Include::MustHave,
+            )]
+            .into(),
+        )
+        .unwrap()
+        .with_drives([Drive::new(&height, Effect::Translate([0.0, 1.0, 0.0]))].into())
+        .with_include(Include::optinal("Frame", false));
+        let assys = [driven("Extension", &height, extension.clone()), hidden];
+        let product = Product::new("Table", [].into(), assys.into());
+        assert!(parameter_lines(&product).is_empty());
+
+        extension.set(true);
+        assert_eq!(parameter_lines(&product).len(), 1);
+    }
+}
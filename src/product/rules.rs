@@ -0,0 +1,436 @@
+//! Configuration rules between options, materials and variants.
+//!
+//! Definitions refer to options, material collections and choices by id, to library
+//! materials by their library id and to variants by name, see
+//! [`ConditionDef`](super::definition::ConditionDef). The conditions are resolved to
+//! ids and indices when the product is built, so a rule applies to every part sharing
+//! that option, collection or choice. Standalone optional parts are options of their own,
+//! referred to by their name.
+//!
+//! ```toml
+//! [[rules]]
+//! kind = "excludes"
+//! when = { collection = "metals", material = "gold" }
+//! then = { collection = "fabrics", material = "pink-fabric" }
+//! reason = "Gold frames are not available with pink fabric"
+//! ```
+use std::collections::HashMap;
+
+use log::warn;
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "render")]
+use three_d::egui::{Checkbox, RadioButton, Ui};
+
+use super::{material::MaterialCollection, Product, SharedChoice};
+
+/// A choice in the configuration
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Condition {
+    /// the optional toggle with this id is on
+    Option { option: Box<str> },
+    /// the material at index `material` is the current material of the collection `collection`
+    Material {
+        collection: Box<str>,
+        material: usize,
+    },
+    /// the variant at index `variant` is selected in the choice `choice`
+    Variant { choice: Box<str>, variant: usize },
+}
+
+impl Condition {
+    pub fn option(id: &str) -> Self {
+        Self::Option { option: id.into() }
+    }
+    pub fn material(collection: &str, material: usize) -> Self {
+        Self::Material {
+            collection: collection.into(),
+            material,
+        }
+    }
+    pub fn variant(choice: &str, variant: usize) -> Self {
+        Self::Variant {
+            choice: choice.into(),
+            variant,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RuleKind {
+    /// `when` can only be chosen while `then` holds
+    Requires,
+    /// `when` and `then` can not be chosen together
+    Excludes,
+    /// choosing `when` also chooses `then`
+    Implies,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rule {
+    pub kind: RuleKind,
+    pub when: Condition,
+    pub then: Condition,
+    /// shown to the user when the rule blocks or corrects a choice
+    pub reason: Box<str>,
+}
+
+impl Rule {
+    fn violated(&self, holds: impl Fn(&Condition) -> bool) -> bool {
+        let (when, then) = (holds(&self.when), holds(&self.then));
+        match self.kind {
+            RuleKind::Requires | RuleKind::Implies => when && !then,
+            RuleKind::Excludes => when && then,
+        }
+    }
+}
+
+pub type Rules = Box<[Rule]>;
+
+/// Choices that would break a rule, with the reason why
+#[derive(Debug, Clone, Default)]
+pub struct Blocked(HashMap<(Condition, bool), Box<str>>);
+
+impl Blocked {
+    /// why setting `condition` to `value` is not allowed, if it is not
+    pub fn reason(&self, condition: &Condition, value: bool) -> Option<&str> {
+        self.0.get(&(condition.clone(), value)).map(|r| r.as_ref())
+    }
+}
+
+impl Product {
+    pub fn rules(&self) -> &[Rule] {
+        &self.rules
+    }
+
+    /// corrections made by the rules on the last change
+    pub fn rule_notes(&self) -> &[Box<str>] {
+        &self.rule_notes
+    }
+
+    fn for_each_collection(&self, mut f: impl FnMut(&MaterialCollection)) {
+        for part in self.parts.iter() {
+            f(part.materials());
+        }
+        for material in self.assys.iter().flat_map(|a| a.materials()) {
+            f(&material.borrow());
+        }
+    }
+
+    fn for_each_collection_mut(&mut self, mut f: impl FnMut(&mut MaterialCollection)) {
+        for part in self.parts.iter_mut() {
+            f(part.materials_mut());
+        }
+        for material in self.assys.iter().flat_map(|a| a.materials()) {
+            f(&mut material.borrow_mut());
+        }
+    }
+
+    fn option_states(&self, id: &str) -> Vec<bool> {
+        let parts = self
+            .parts
+            .iter()
+            .filter(|p| p.is_optional() && p.name() == id)
+            .map(|p| p.opt_in());
+        let assys = self
+            .assys
+            .iter()
+            .flat_map(|a| a.toggles())
+            .filter(|inc| inc.id() == Some(id))
+            .map(|inc| inc.is_show());
+        parts.chain(assys).collect()
+    }
+
+    /// the choices with id `id`, the same choice is shared by all assemblies using it
+    fn choices_with_id<'a>(&'a self, id: &'a str) -> impl Iterator<Item = &'a SharedChoice> {
+        self.assys
+            .iter()
+            .flat_map(|a| a.choices())
+            .filter(move |c| c.borrow().id() == id)
+    }
+
+    /// whether `condition` holds in the current configuration
+    pub fn holds(&self, condition: &Condition) -> bool {
+        match condition {
+            Condition::Option { option } => self.option_states(option).into_iter().any(|on| on),
+            Condition::Material {
+                collection,
+                material,
+            } => {
+                let mut holds = false;
+                self.for_each_collection(|c| {
+                    holds |= c.id() == collection.as_ref() && c.current_material == *material
+                });
+                holds
+            }
+            Condition::Variant { choice, variant } => self
+                .choices_with_id(choice)
+                .any(|c| c.borrow().selected == *variant),
+        }
+    }
+
+    /// whether `condition` refers to an existing option, material or variant
+    pub fn knows(&self, condition: &Condition) -> bool {
+        match condition {
+            Condition::Option { option } => !self.option_states(option).is_empty(),
+            Condition::Material {
+                collection,
+                material,
+            } => {
+                let mut known = false;
+                self.for_each_collection(|c| {
+                    known |= c.id() == collection.as_ref() && *material < c.len()
+                });
+                known
+            }
+            Condition::Variant { choice, variant } => self
+                .choices_with_id(choice)
+                .any(|c| *variant < c.borrow().len()),
+        }
+    }
+
+    /// the first rule the current configuration breaks
+    pub fn broken_rule(&self) -> Option<&Rule> {
+        self.rules
+            .iter()
+            .find(|rule| rule.violated(|c| self.holds(c)))
+    }
+
+    /// whether `condition` would hold if `choice` was set to `value`
+    fn holds_if(&self, condition: &Condition, choice: &Condition, value: bool) -> bool {
+        if condition == choice {
+            return value;
+        }
+        match (condition, choice) {
+            // picking a material deselects the others in its collection
+            (
+                Condition::Material { collection, .. },
+                Condition::Material {
+                    collection: choice_collection,
+                    ..
+                },
+            ) if value && collection == choice_collection => false,
+            // as does picking a variant
+            (
+                Condition::Variant { choice, .. },
+                Condition::Variant {
+                    choice: picked_choice,
+                    ..
+                },
+            ) if value && choice == picked_choice => false,
+            _ => self.holds(condition),
+        }
+    }
+
+    /// the first rule broken by setting `choice` to `value`
+    pub fn blocking_rule(&self, choice: &Condition, value: bool) -> Option<&Rule> {
+        self.rules.iter().find(|rule| {
+            // implied choices are corrected instead of blocked
+            let corrected = rule.kind == RuleKind::Implies && value && *choice == rule.when;
+            !corrected
+                && rule.violated(|c| self.holds_if(c, choice, value))
+                && !rule.violated(|c| self.holds(c))
+        })
+    }
+
+    /// every option state and material that is blocked by a rule
+    pub fn blocked(&self) -> Blocked {
+        let mut blocked = Blocked::default();
+        if self.rules.is_empty() {
+            return blocked;
+        }
+        let mut choices = Vec::new();
+        for name in self
+            .parts
+            .iter()
+            .filter(|p| p.is_optional())
+            .map(|p| p.name())
+        {
+            choices.push(Condition::option(name));
+        }
+        for id in self
+            .assys
+            .iter()
+            .flat_map(|a| a.toggles())
+            .filter_map(|i| i.id())
+        {
+            choices.push(Condition::option(id));
+        }
+        self.for_each_collection(|c| {
+            for i in 0..c.len() {
+                choices.push(Condition::material(c.id(), i));
+            }
+        });
+        for choice in self.assys.iter().flat_map(|a| a.choices()) {
+            let choice = choice.borrow();
+            for i in 0..choice.len() {
+                choices.push(Condition::variant(choice.id(), i));
+            }
+        }
+        for choice in choices {
+            for value in [true, false] {
+                if let Some(rule) = self.blocking_rule(&choice, value) {
+                    blocked
+                        .0
+                        .insert((choice.clone(), value), rule.reason.clone());
+                }
+            }
+        }
+        blocked
+    }
+
+    /// sets `condition` to `value`, returns false if nothing could be changed
+    fn set_condition(&mut self, condition: &Condition, value: bool) -> bool {
+        match condition {
+            Condition::Option { option } => {
+                let mut changed = false;
+                for part in self
+                    .parts
+                    .iter_mut()
+                    .filter(|p| p.name() == option.as_ref())
+                {
+                    part.set_opt_in(value);
+                    changed |= part.opt_in() == value;
+                }
+                for include in self.assys.iter().flat_map(|a| a.toggles()) {
+                    if include.id() == Some(option.as_ref()) {
+                        include.set(value);
+                        changed = true;
+                    }
+                }
+                changed
+            }
+            Condition::Material {
+                collection,
+                material,
+            } => {
+                let mut len = 0;
+                self.for_each_collection(|c| {
+                    if c.id() == collection.as_ref() {
+                        len = len.max(c.len());
+                    }
+                });
+                let choice = |i| Condition::material(collection, i);
+                let Some(replacement) = self.replacement(*material, len, value, choice) else {
+                    return false;
+                };
+                let mut changed = false;
+                self.for_each_collection_mut(|c| {
+                    if c.id() == collection.as_ref() {
+                        changed |= c.select(replacement);
+                    }
+                });
+                changed
+            }
+            Condition::Variant { choice, variant } => {
+                let len = self
+                    .choices_with_id(choice)
+                    .map(|c| c.borrow().len())
+                    .max()
+                    .unwrap_or(0);
+                let condition = |i| Condition::variant(choice, i);
+                let Some(replacement) = self.replacement(*variant, len, value, condition) else {
+                    return false;
+                };
+                let mut changed = false;
+                for shared in self.choices_with_id(choice) {
+                    changed |= shared.borrow_mut().select(replacement);
+                }
+                changed
+            }
+        }
+    }
+
+    /// the index to select for setting the entry `index` of `len` to `value`,
+    /// deselecting picks the first other entry that breaks no rule
+    fn replacement(
+        &self,
+        index: usize,
+        len: usize,
+        value: bool,
+        condition: impl Fn(usize) -> Condition,
+    ) -> Option<usize> {
+        if value {
+            return Some(index);
+        }
+        let mut candidates = (0..len).filter(|i| *i != index);
+        let allowed = candidates.clone().find(|i| {
+            let choice = condition(*i);
+            self.rules
+                .iter()
+                .all(|rule| !rule.violated(|c| self.holds_if(c, &choice, true)))
+        });
+        allowed.or_else(|| candidates.next())
+    }
+
+    /// corrects the configuration until no rule is broken,
+    /// returns the reasons of the applied corrections
+    pub fn enforce_rules(&mut self) -> Vec<Box<str>> {
+        let rules = self.rules.clone();
+        let mut notes = Vec::new();
+        // every correction can trigger another rule, but not indefinitely
+        for _ in 0..=rules.len() {
+            let Some(rule) = rules.iter().find(|r| r.violated(|c| self.holds(c))) else {
+                break;
+            };
+            let fixed = match rule.kind {
+                RuleKind::Implies => self.set_condition(&rule.then, true),
+                RuleKind::Requires | RuleKind::Excludes => self.set_condition(&rule.when, false),
+            };
+            notes.push(rule.reason.clone());
+            if !fixed {
+                warn!(
+                    "could not correct the configuration for rule: {}",
+                    rule.reason
+                );
+                break;
+            }
+        }
+        notes
+    }
+
+    /// enforces the rules if the configuration changed since the last call
+    pub fn apply_rules(&mut self) {
+        if self.rules.is_empty() {
+            return;
+        }
+        let config = self.configuration();
+        if self.rules_checked.as_ref() == Some(&config) {
+            return;
+        }
+        self.rule_notes = self.enforce_rules().into();
+        self.rules_checked = Some(self.configuration());
+    }
+}
+
+#[cfg(feature = "render")]
+impl Blocked {
+    /// a checkbox that is disabled, with the reason on hover, if toggling it is blocked
+    pub fn checkbox(&self, ui: &mut Ui, value: &mut bool, condition: &Condition, text: &str) {
+        let reason = self.reason(condition, !*value);
+        let response = ui.add_enabled(reason.is_none(), Checkbox::new(value, text));
+        if let Some(reason) = reason {
+            response.on_disabled_hover_text(reason);
+        }
+    }
+
+    /// a radio button for `option`, disabled with the reason on hover if picking it is blocked
+    pub fn radio(
+        &self,
+        ui: &mut Ui,
+        value: &mut usize,
+        option: usize,
+        condition: &Condition,
+        text: &str,
+    ) {
+        let selected = *value == option;
+        let reason = self.reason(condition, true).filter(|_| !selected);
+        let response = ui.add_enabled(reason.is_none(), RadioButton::new(selected, text));
+        if response.clicked() {
+            *value = option;
+        }
+        if let Some(reason) = reason {
+            response.on_disabled_hover_text(reason);
+        }
+    }
+}
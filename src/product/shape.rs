@@ -1,4 +1,25 @@
-use three_d_asset::{Positions, TriMesh};
+//! Parametric meshes to prototype products without CAD files.
+//!
+//! Every primitive is centered at the origin with y up and has normals and uvs.
+//! Round shapes wrap the u coordinate once around the y axis, the v coordinate runs
+//! from the bottom to the top of each surface. Definitions can use a [`Shape`]
+//! wherever they take a mesh path:
+//!
+//! ```toml
+//! [[assys.parts]]
+//! name = "Leg"
+//! mesh = { cylinder = { radius = 20.0, height = 700.0 } }
+//! material = "metals"
+//!
+//! [[assys.parts]]
+//! name = "Top"
+//! mesh = { rounded_box = { size = [1600.0, 30.0, 800.0], radius = 10.0 } }
+//! material = "wood"
+//! ```
+use std::f32::consts::{FRAC_PI_2, TAU};
+
+use serde::{Deserialize, Serialize};
+use three_d_asset::{vec2, vec3, Indices, Positions, TriMesh, Vec2, Vec3};
 
 pub fn cube(dx: f32, dy: f32, dz: f32) -> TriMesh {
     let mut shape = TriMesh::cube();
@@ -24,3 +45,450 @@ pub fn cube(dx: f32, dy: f32, dz: f32) -> TriMesh {
     shape.positions = pos;
     shape
 }
+
+/// A generated mesh and its dimensions
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Shape {
+    /// closed at both ends
+    Cylinder {
+        radius: f32,
+        height: f32,
+        #[serde(default = "default_segments")]
+        segments: u32,
+    },
+    /// a hollow cylinder, closed by rings at both ends
+    Tube {
+        outer_radius: f32,
+        inner_radius: f32,
+        height: f32,
+        #[serde(default = "default_segments")]
+        segments: u32,
+    },
+    /// a box with all edges and corners rounded by `radius`
+    RoundedBox {
+        size: [f32; 3],
+        radius: f32,
+        /// steps per rounded edge
+        #[serde(default = "default_corner_segments")]
+        segments: u32,
+    },
+    /// a ring around the y axis, `radius` is measured to the center of the tube
+    Torus {
+        radius: f32,
+        tube_radius: f32,
+        #[serde(default = "default_segments")]
+        segments: u32,
+        #[serde(default = "default_tube_segments")]
+        tube_segments: u32,
+    },
+    /// a flat rectangle in the xz plane facing up, with uvs from 0 to 1
+    Plane { size: [f32; 2] },
+    /// a cylinder with half spheres at both ends, `height` includes the ends
+    Capsule {
+        radius: f32,
+        height: f32,
+        #[serde(default = "default_segments")]
+        segments: u32,
+    },
+}
+
+/// the most segments around a round shape, more only slow down loading and rendering
+const MAX_SEGMENTS: u32 = 256;
+/// the most segments per rounded edge of a box, each adds a row and column to all six faces
+const MAX_CORNER_SEGMENTS: u32 = 64;
+
+fn default_segments() -> u32 {
+    32
+}
+
+fn default_corner_segments() -> u32 {
+    4
+}
+
+fn default_tube_segments() -> u32 {
+    16
+}
+
+impl Shape {
+    /// whether the dimensions are positive and fit together and the segments are in range
+    pub fn is_valid(&self) -> bool {
+        fn positive(values: &[f32]) -> bool {
+            values.iter().all(|v| v.is_finite() && *v > 0.0)
+        }
+        match *self {
+            Shape::Cylinder {
+                radius,
+                height,
+                segments,
+            } => positive(&[radius, height]) && (3..=MAX_SEGMENTS).contains(&segments),
+            Shape::Tube {
+                outer_radius,
+                inner_radius,
+                height,
+                segments,
+            } => {
+                positive(&[outer_radius, inner_radius, height])
+                    && inner_radius < outer_radius
+                    && (3..=MAX_SEGMENTS).contains(&segments)
+            }
+            Shape::RoundedBox {
+                size,
+                radius,
+                segments,
+            } => {
+                let smallest = size.iter().fold(f32::INFINITY, |a, b| a.min(*b));
+                positive(&size)
+                    && radius.is_finite()
+                    && (0.0..=smallest / 2.0).contains(&radius)
+                    && (1..=MAX_CORNER_SEGMENTS).contains(&segments)
+            }
+            Shape::Torus {
+                radius,
+                tube_radius,
+                segments,
+                tube_segments,
+            } => {
+                positive(&[radius, tube_radius])
+                    && tube_radius < radius
+                    && (3..=MAX_SEGMENTS).contains(&segments)
+                    && (3..=MAX_SEGMENTS).contains(&tube_segments)
+            }
+            Shape::Plane { size } => positive(&size),
+            Shape::Capsule {
+                radius,
+                height,
+                segments,
+            } => {
+                positive(&[radius, height])
+                    && height >= 2.0 * radius
+                    && (4..=MAX_SEGMENTS).contains(&segments)
+            }
+        }
+    }
+
+    pub fn mesh(&self) -> TriMesh {
+        match *self {
+            Shape::Cylinder {
+                radius,
+                height,
+                segments,
+            } => cylinder(radius, height, segments),
+            Shape::Tube {
+                outer_radius,
+                inner_radius,
+                height,
+                segments,
+            } => tube(outer_radius, inner_radius, height, segments),
+            Shape::RoundedBox {
+                size,
+                radius,
+                segments,
+            } => rounded_box(size, radius, segments),
+            Shape::Torus {
+                radius,
+                tube_radius,
+                segments,
+                tube_segments,
+            } => torus(radius, tube_radius, segments, tube_segments),
+            Shape::Plane { size } => plane(size[0], size[1]),
+            Shape::Capsule {
+                radius,
+                height,
+                segments,
+            } => capsule(radius, height, segments),
+        }
+    }
+}
+
+/// normals of the profiles revolved around the y axis, as (radius, y)
+const OUT: Vec2 = Vec2 { x: 1.0, y: 0.0 };
+const IN: Vec2 = Vec2 { x: -1.0, y: 0.0 };
+const UP: Vec2 = Vec2 { x: 0.0, y: 1.0 };
+const DOWN: Vec2 = Vec2 { x: 0.0, y: -1.0 };
+
+pub fn cylinder(radius: f32, height: f32, segments: u32) -> TriMesh {
+    let (r, y) = (radius, height / 2.0);
+    let mut mesh = MeshBuilder::default();
+    mesh.revolve(&[(vec2(0.0, -y), DOWN), (vec2(r, -y), DOWN)], segments);
+    mesh.revolve(&[(vec2(r, -y), OUT), (vec2(r, y), OUT)], segments);
+    mesh.revolve(&[(vec2(r, y), UP), (vec2(0.0, y), UP)], segments);
+    mesh.build()
+}
+
+pub fn tube(outer_radius: f32, inner_radius: f32, height: f32, segments: u32) -> TriMesh {
+    let (outer, inner, y) = (outer_radius, inner_radius, height / 2.0);
+    let mut mesh = MeshBuilder::default();
+    mesh.revolve(&[(vec2(outer, -y), OUT), (vec2(outer, y), OUT)], segments);
+    mesh.revolve(&[(vec2(outer, y), UP), (vec2(inner, y), UP)], segments);
+    mesh.revolve(&[(vec2(inner, y), IN), (vec2(inner, -y), IN)], segments);
+    mesh.revolve(
+        &[(vec2(inner, -y), DOWN), (vec2(outer, -y), DOWN)],
+        segments,
+    );
+    mesh.build()
+}
+
+pub fn torus(radius: f32, tube_radius: f32, segments: u32, tube_segments: u32) -> TriMesh {
+    let profile: Vec<(Vec2, Vec2)> = (0..=tube_segments)
+        .map(|k| {
+            let angle = TAU * k as f32 / tube_segments as f32;
+            let normal = vec2(angle.cos(), angle.sin());
+            (vec2(radius, 0.0) + normal * tube_radius, normal)
+        })
+        .collect();
+    let mut mesh = MeshBuilder::default();
+    mesh.revolve(&profile, segments);
+    mesh.build()
+}
+
+pub fn capsule(radius: f32, height: f32, segments: u32) -> TriMesh {
+    let half = height / 2.0 - radius;
+    // steps per quarter circle of the ends
+    let steps = (segments / 4).max(1);
+    let quarter = |from: f32, center: f32| {
+        (0..=steps).map(move |k| {
+            let angle = from + FRAC_PI_2 * k as f32 / steps as f32;
+            let normal = vec2(angle.cos(), angle.sin());
+            (vec2(0.0, center) + normal * radius, normal)
+        })
+    };
+    let profile: Vec<(Vec2, Vec2)> = quarter(-FRAC_PI_2, -half)
+        .chain(quarter(0.0, half))
+        .collect();
+    let mut mesh = MeshBuilder::default();
+    mesh.revolve(&profile, segments);
+    mesh.build()
+}
+
+pub fn plane(width: f32, depth: f32) -> TriMesh {
+    let (x, z) = (width / 2.0, depth / 2.0);
+    let mut mesh = MeshBuilder::default();
+    mesh.grid(2, 2, |i, j| {
+        let (u, v) = (j as f32, i as f32);
+        let position = vec3(-x + 2.0 * x * u, 0.0, z - 2.0 * z * v);
+        (position, vec3(0.0, 1.0, 0.0), vec2(u, v))
+    });
+    mesh.build()
+}
+
+pub fn rounded_box(size: [f32; 3], radius: f32, segments: u32) -> TriMesh {
+    let half = size.map(|s| s / 2.0);
+    let inner = half.map(|h| h - radius);
+    // coordinates along every axis, dense where the edges are rounded
+    let coordinates: Vec<Vec<f32>> = inner
+        .iter()
+        .map(|&inner| {
+            let round = |k: u32| radius * (FRAC_PI_2 * k as f32 / segments as f32).sin();
+            let lower = (0..=segments).rev().map(|k| -inner - round(k));
+            let upper = (0..=segments).map(|k| inner + round(k));
+            lower.chain(upper).collect()
+        })
+        .collect();
+
+    let mut mesh = MeshBuilder::default();
+    // the axis facing out and two axes along the face whose cross product faces out
+    let faces = [
+        (0, 1.0, 1, 2),
+        (0, -1.0, 2, 1),
+        (1, 1.0, 2, 0),
+        (1, -1.0, 0, 2),
+        (2, 1.0, 0, 1),
+        (2, -1.0, 1, 0),
+    ];
+    for (axis, sign, a, b) in faces {
+        let (along_a, along_b) = (&coordinates[a], &coordinates[b]);
+        mesh.grid(along_b.len(), along_a.len(), |i, j| {
+            let mut point = [0.0; 3];
+            point[axis] = sign * half[axis];
+            point[a] = along_a[j];
+            point[b] = along_b[i];
+            // pull the point onto the rounded surface around the inner box
+            let clamped = vec3(
+                point[0].clamp(-inner[0], inner[0]),
+                point[1].clamp(-inner[1], inner[1]),
+                point[2].clamp(-inner[2], inner[2]),
+            );
+            let offset = vec3(point[0], point[1], point[2]) - clamped;
+            let length = (offset.x * offset.x + offset.y * offset.y + offset.z * offset.z).sqrt();
+            let (position, normal) = if length > 0.0 {
+                let normal = offset / length;
+                (clamped + normal * radius, normal)
+            } else {
+                let mut normal = [0.0; 3];
+                normal[axis] = sign;
+                (clamped, vec3(normal[0], normal[1], normal[2]))
+            };
+            let uv = vec2(
+                (point[a] + half[a]) / size[a],
+                (point[b] + half[b]) / size[b],
+            );
+            (position, normal, uv)
+        });
+    }
+    mesh.build()
+}
+
+/// Vertices and triangles of a mesh being generated
+#[derive(Default)]
+struct MeshBuilder {
+    positions: Vec<Vec3>,
+    normals: Vec<Vec3>,
+    uvs: Vec<Vec2>,
+    indices: Vec<u32>,
+}
+
+impl MeshBuilder {
+    /// a grid of `rows` times `columns` vertices, the cross product of the column and
+    /// the row direction is the front of its triangles
+    fn grid(
+        &mut self,
+        rows: usize,
+        columns: usize,
+        mut vertex: impl FnMut(usize, usize) -> (Vec3, Vec3, Vec2),
+    ) {
+        let first = self.positions.len() as u32;
+        for i in 0..rows {
+            for j in 0..columns {
+                let (position, normal, uv) = vertex(i, j);
+                self.positions.push(position);
+                self.normals.push(normal);
+                self.uvs.push(uv);
+            }
+        }
+        let columns = columns as u32;
+        for i in 0..rows.saturating_sub(1) as u32 {
+            for j in 0..columns.saturating_sub(1) {
+                let a = first + i * columns + j;
+                let (b, c, d) = (a + 1, a + columns + 1, a + columns);
+                self.indices.extend([a, b, c, a, c, d]);
+            }
+        }
+    }
+
+    /// revolves a smooth `profile` of (radius, y) points with their normals around the y axis,
+    /// the normals have to point to the right of the direction the profile is walked in
+    fn revolve(&mut self, profile: &[(Vec2, Vec2)], segments: u32) {
+        let mut lengths = vec![0.0];
+        for pair in profile.windows(2) {
+            let step = pair[1].0 - pair[0].0;
+            let length = lengths[lengths.len() - 1] + (step.x * step.x + step.y * step.y).sqrt();
+            lengths.push(length);
+        }
+        let total = lengths[lengths.len() - 1].max(f32::EPSILON);
+        let columns = segments as usize + 1;
+        self.grid(profile.len(), columns, |i, j| {
+            let (point, normal) = profile[i];
+            let u = j as f32 / segments as f32;
+            let (sin, cos) = (TAU * u).sin_cos();
+            let position = vec3(point.x * sin, point.y, point.x * cos);
+            let normal = vec3(normal.x * sin, normal.y, normal.x * cos);
+            (position, normal, vec2(u, lengths[i] / total))
+        });
+    }
+
+    fn build(self) -> TriMesh {
+        TriMesh {
+            positions: Positions::F32(self.positions),
+            indices: Indices::U32(self.indices),
+            normals: Some(self.normals),
+            uvs: Some(self.uvs),
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use three_d_asset::InnerSpace;
+
+    use super::*;
+
+    fn shapes() -> [Shape; 6] {
+        [
+            Shape::Cylinder {
+                radius: 20.0,
+                height: 700.0,
+                segments: default_segments(),
+            },
+            Shape::Tube {
+                outer_radius: 20.0,
+                inner_radius: 16.0,
+                height: 700.0,
+                segments: default_segments(),
+            },
+            Shape::RoundedBox {
+                size: [1600.0, 30.0, 800.0],
+                radius: 10.0,
+                segments: default_corner_segments(),
+            },
+            Shape::Torus {
+                radius: 100.0,
+                tube_radius: 20.0,
+                segments: default_segments(),
+                tube_segments: default_tube_segments(),
+            },
+            Shape::Plane {
+                size: [400.0, 300.0],
+            },
+            Shape::Capsule {
+                radius: 20.0,
+                height: 300.0,
+                segments: default_segments(),
+            },
+        ]
+    }
+
+    #[test]
+    fn normals_face_the_front_of_the_triangles() {
+        for shape in shapes() {
+            assert!(shape.is_valid(), "{shape:?}");
+            let mesh = shape.mesh();
+            let positions = mesh.positions.to_f32();
+            let normals = mesh.normals.as_ref().unwrap();
+            let indices = mesh.indices.to_u32().unwrap();
+            for triangle in indices.chunks(3) {
+                let [a, b, c] = [0, 1, 2].map(|k| triangle[k] as usize);
+                let front = (positions[b] - positions[a]).cross(positions[c] - positions[a]);
+                // the poles of revolved shapes have triangles without area
+                if front.magnitude() < 1e-6 {
+                    continue;
+                }
+                let normal = normals[a] + normals[b] + normals[c];
+                assert!(front.dot(normal) > 0.0, "{shape:?} triangle {triangle:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn uvs_stay_in_the_unit_square() {
+        for shape in shapes() {
+            let mesh = shape.mesh();
+            for uv in mesh.uvs.as_ref().unwrap() {
+                // up to rounding at the ends of the rounded edges
+                let inside = |t: f32| (-1e-5..=1.0 + 1e-5).contains(&t);
+                assert!(inside(uv.x) && inside(uv.y), "{shape:?} uv {uv:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn segments_are_capped() {
+        let cylinder = |segments| Shape::Cylinder {
+            radius: 20.0,
+            height: 700.0,
+            segments,
+        };
+        assert!(cylinder(MAX_SEGMENTS).is_valid());
+        assert!(!cylinder(MAX_SEGMENTS + 1).is_valid());
+        assert!(!cylinder(u32::MAX).is_valid());
+        assert!(!cylinder(2).is_valid());
+        let rounded_box = |segments| Shape::RoundedBox {
+            size: [100.0; 3],
+            radius: 10.0,
+            segments,
+        };
+        assert!(rounded_box(MAX_CORNER_SEGMENTS).is_valid());
+        assert!(!rounded_box(MAX_CORNER_SEGMENTS + 1).is_valid());
+    }
+}
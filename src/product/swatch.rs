@@ -0,0 +1,171 @@
+//! A grid of swatches to pick the material of a collection by its color or texture.
+use three_d::egui::{
+    pos2, Color32, ColorImage, Id, Rect, Response, Rounding, Sense, Stroke, TextureHandle,
+    TextureOptions, Ui, Vec2,
+};
+use three_d_asset::{Texture2D, TextureData};
+
+use super::{
+    material::{Material, MaterialCollection},
+    rules::{Blocked, Condition},
+};
+
+const SWATCH_SIZE: f32 = 28.0;
+/// textures are shrunk to at most this many pixels per side for the swatch
+const THUMBNAIL_SIZE: usize = 64;
+
+impl MaterialCollection {
+    /// a swatch per material with the name of the current one below,
+    /// materials blocked by a rule are faded and can not be picked
+    pub fn add_swatches(&mut self, ui: &mut Ui, blocked: &Blocked) {
+        let mut chosen = None;
+        ui.horizontal_wrapped(|ui| {
+            for (i, material) in self.options().iter().enumerate() {
+                let selected = self.current_material == i;
+                let condition = Condition::material(self.id(), i);
+                let reason = blocked.reason(&condition, true).filter(|_| !selected);
+                if swatch(ui, material, selected, reason).clicked() {
+                    chosen = Some(i);
+                }
+            }
+        });
+        if let Some(index) = chosen {
+            self.select(index);
+        }
+        ui.label(self.current().name());
+        if self.custom_selected() {
+            self.add_color_picker(ui);
+        }
+    }
+
+    /// a color picker for the custom entry, or its palette if it has one
+    fn add_color_picker(&mut self, ui: &mut Ui) {
+        let Some(current) = self.custom_color() else {
+            return;
+        };
+        if self.palette().is_empty() {
+            let mut rgb = current;
+            ui.horizontal(|ui| {
+                ui.color_edit_button_srgb(&mut rgb);
+                ui.label(self.current().hex());
+            });
+            if rgb != current {
+                self.set_custom_color(rgb);
+            }
+            return;
+        }
+        let mut chosen = None;
+        ui.horizontal_wrapped(|ui| {
+            for rgb in self.palette().iter().copied() {
+                if color_swatch(ui, rgb, rgb == current).clicked() {
+                    chosen = Some(rgb);
+                }
+            }
+        });
+        if let Some(rgb) = chosen {
+            self.set_custom_color(rgb);
+        }
+    }
+}
+
+/// a small swatch of a plain palette color
+fn color_swatch(ui: &mut Ui, rgb: [u8; 3], selected: bool) -> Response {
+    let size = Vec2::splat(SWATCH_SIZE * 0.75);
+    let (rect, response) = ui.allocate_exact_size(size, Sense::click());
+    if ui.is_rect_visible(rect) {
+        let rounding = Rounding::same(size.x / 2.0);
+        let painter = ui.painter();
+        painter.rect_filled(rect, rounding, Color32::from_rgb(rgb[0], rgb[1], rgb[2]));
+        let stroke = if selected {
+            Stroke::new(3.0, ui.visuals().selection.stroke.color)
+        } else {
+            ui.visuals().widgets.style(&response).bg_stroke
+        };
+        painter.rect_stroke(rect, rounding, stroke);
+    }
+    let [r, g, b] = rgb;
+    response.on_hover_text(format!("#{r:02x}{g:02x}{b:02x}"))
+}
+
+fn swatch(ui: &mut Ui, material: &Material, selected: bool, blocked: Option<&str>) -> Response {
+    let sense = match blocked {
+        Some(_) => Sense::hover(),
+        None => Sense::click(),
+    };
+    let (rect, response) = ui.allocate_exact_size(Vec2::splat(SWATCH_SIZE), sense);
+    if ui.is_rect_visible(rect) {
+        let rounding = Rounding::same(4.0);
+        let fade = if blocked.is_some() { 0.3 } else { 1.0 };
+        let painter = ui.painter();
+        match thumbnail(ui, material) {
+            Some(texture) => {
+                let uv = Rect::from_min_max(pos2(0.0, 0.0), pos2(1.0, 1.0));
+                let tint = material.color32().gamma_multiply(fade);
+                painter.image(texture.id(), rect, uv, tint);
+            }
+            None => painter.rect_filled(rect, rounding, material.color32().gamma_multiply(fade)),
+        }
+        let stroke = if selected {
+            Stroke::new(3.0, ui.visuals().selection.stroke.color)
+        } else {
+            ui.visuals().widgets.style(&response).bg_stroke
+        };
+        painter.rect_stroke(rect, rounding, stroke);
+    }
+
+    let mut tooltip = material.name().to_string();
+    if !material.price().is_zero() {
+        tooltip += &format!(" (+{})", material.price());
+    }
+    if let Some(reason) = blocked {
+        tooltip += &format!("\n{reason}");
+    }
+    response.on_hover_text(tooltip)
+}
+
+/// the albedo texture of `material` as an egui texture, uploaded once per path
+fn thumbnail(ui: &Ui, material: &Material) -> Option<TextureHandle> {
+    let map = material.textures()?.albedo.as_ref()?;
+    let texture = map.texture()?;
+    let id = Id::new(("swatch", map.path()));
+    if let Some(handle) = ui.data(|d| d.get_temp::<TextureHandle>(id)) {
+        return Some(handle);
+    }
+    let image = color_image(texture)?;
+    let handle = ui
+        .ctx()
+        .load_texture(map.path(), image, TextureOptions::LINEAR);
+    ui.data_mut(|d| d.insert_temp(id, handle.clone()));
+    Some(handle)
+}
+
+/// a nearest neighbour downscaled copy of an 8 bit `texture`
+fn color_image(texture: &Texture2D) -> Option<ColorImage> {
+    let (width, height) = (texture.width as usize, texture.height as usize);
+    if width == 0 || height == 0 {
+        return None;
+    }
+    let step = (width.max(height) / THUMBNAIL_SIZE).max(1);
+    let size = [width.div_ceil(step), height.div_ceil(step)];
+    let pixel = |i: usize| -> Option<Color32> {
+        match &texture.data {
+            TextureData::RU8(data) => Some(Color32::from_gray(data[i])),
+            TextureData::RgbU8(data) => {
+                let [r, g, b] = data[i];
+                Some(Color32::from_rgb(r, g, b))
+            }
+            TextureData::RgbaU8(data) => {
+                let [r, g, b, a] = data[i];
+                Some(Color32::from_rgba_unmultiplied(r, g, b, a))
+            }
+            _ => None,
+        }
+    };
+    let mut pixels = Vec::with_capacity(size[0] * size[1]);
+    for y in (0..height).step_by(step) {
+        for x in (0..width).step_by(step) {
+            pixels.push(pixel(y * width + x)?);
+        }
+    }
+    Some(ColorImage { size, pixels })
+}
@@ -0,0 +1,133 @@
+//! Placement of a mesh as translation, rotation and scale.
+//!
+//! ```toml
+//! [[assys.parts]]
+//! name = "Left Arm"
+//! mesh = "arm.obj"
+//! material = "metals"
+//! transform = { translation = [-250.0, 0.0, 0.0], rotation = [0.0, 0.0, 180.0] }
+//! ```
+//!
+//! Repeated parts list a transform per copy, relative to the transform of the part:
+//!
+//! ```toml
+//! [[assys.parts]]
+//! name = "Castor"
+//! mesh = "castor.obj"
+//! material = "plastic"
+//! instances = [
+//!     { translation = [300.0, 0.0, 0.0] },
+//!     { translation = [0.0, 300.0, 0.0] },
+//!     { translation = [-300.0, 0.0, 0.0] },
+//! ]
+//! ```
+use serde::{Deserialize, Serialize};
+use three_d_asset::{
+    degrees, vec3, AxisAlignedBoundingBox as AABB, InnerSpace, Mat4, SquareMatrix,
+};
+
+/// Scales, then rotates around x, y and z in that order, then translates
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Transform {
+    #[serde(default)]
+    pub translation: [f32; 3],
+    /// angles around x, y and z in degrees
+    #[serde(default)]
+    pub rotation: [f32; 3],
+    #[serde(default = "unit_scale")]
+    pub scale: [f32; 3],
+}
+
+fn unit_scale() -> [f32; 3] {
+    [1.0; 3]
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+impl Transform {
+    pub const IDENTITY: Transform = Transform {
+        translation: [0.0; 3],
+        rotation: [0.0; 3],
+        scale: [1.0; 3],
+    };
+
+    pub fn from_translation(x: f32, y: f32, z: f32) -> Self {
+        Self {
+            translation: [x, y, z],
+            ..Self::IDENTITY
+        }
+    }
+    /// sets the angles around x, y and z in degrees
+    pub fn with_rotation(mut self, x: f32, y: f32, z: f32) -> Self {
+        self.rotation = [x, y, z];
+        self
+    }
+    pub fn with_scale(mut self, x: f32, y: f32, z: f32) -> Self {
+        self.scale = [x, y, z];
+        self
+    }
+    pub fn is_identity(&self) -> bool {
+        *self == Self::IDENTITY
+    }
+
+    pub fn matrix(&self) -> Mat4 {
+        let [tx, ty, tz] = self.translation;
+        let [rx, ry, rz] = self.rotation;
+        let [sx, sy, sz] = self.scale;
+        Mat4::from_translation(vec3(tx, ty, tz))
+            * Mat4::from_angle_z(degrees(rz))
+            * Mat4::from_angle_y(degrees(ry))
+            * Mat4::from_angle_x(degrees(rx))
+            * Mat4::from_nonuniform_scale(sx, sy, sz)
+    }
+
+    /// the translation, rotation and scale of `matrix`, `None` if it mirrors or shears
+    pub(crate) fn from_matrix(matrix: Mat4) -> Option<Self> {
+        if matrix.determinant() <= 0.0 {
+            return None;
+        }
+        let columns = [matrix.x, matrix.y, matrix.z].map(|c| c.truncate());
+        let scale = columns.map(|c| c.magnitude());
+        let [x, y, z] = [0, 1, 2].map(|i| columns[i] / scale[i]);
+        // the rotation is around z after y after x, see `matrix`
+        let ry = (-x.z).clamp(-1.0, 1.0).asin();
+        let (rx, rz) = if x.z.abs() < 0.9999 {
+            (y.z.atan2(z.z), x.y.atan2(x.x))
+        } else {
+            // y is turned by a right angle, only the sum of the other two angles is known
+            ((-z.y).atan2(y.y), 0.0)
+        };
+        let transform = Self {
+            translation: [matrix.w.x, matrix.w.y, matrix.w.z],
+            rotation: [rx, ry, rz].map(f32::to_degrees),
+            scale,
+        };
+        let rebuilt = transform.matrix();
+        let close = (0..4).all(|c| {
+            (0..4)
+                .all(|r| (rebuilt[c][r] - matrix[c][r]).abs() <= 1e-4 * (1.0 + matrix[c][r].abs()))
+        });
+        close.then_some(transform)
+    }
+
+    /// the box around `bb` once it is transformed by self
+    pub fn bounding_box(&self, mut bb: AABB) -> AABB {
+        if !self.is_identity() {
+            bb.transform(&self.matrix());
+        }
+        bb
+    }
+
+    pub(crate) fn is_finite(&self) -> bool {
+        let mut values = self
+            .translation
+            .iter()
+            .chain(&self.rotation)
+            .chain(&self.scale);
+        values.all(|v| v.is_finite())
+    }
+}
@@ -1,38 +1,115 @@
-use log::info;
+use log::{error, info};
 use three_d::{
-    egui::SidePanel, AmbientLight, Camera, ClearState, FrameOutput, OrbitControl, Skybox, Window,
+    egui::{SidePanel, TextEdit, Ui},
+    AmbientLight, Camera, ClearState, Event, FrameOutput, Key, OrbitControl, Skybox, Window,
     WindowSettings,
 };
-use three_d_asset::{degrees, vec3, Srgba, Viewport};
+use three_d_asset::{degrees, vec3, Srgba, Texture2D, Viewport};
 
 use crate::product::{self, Product};
+use crate::save::save_file;
 
-pub async fn render(mut product: Product) {
-    let window = Window::new(WindowSettings {
-        title: "Product Config".to_string(),
-        ..Default::default()
-    })
-    .unwrap();
-    let context = window.gl();
-
+/// loads the environment map used for lighting
+async fn load_environment() -> crate::Result<Texture2D> {
     let asset_paths = [
         "brown_photostudio_02_2k.hdr", // Source: https://polyhaven.com/
     ];
     #[cfg(not(target_arch = "wasm32"))]
     let asset_paths = asset_paths.map(|p| format!("./assets/{}", p));
 
-    let mut loaded = if let Ok(loaded) = three_d_asset::io::load_async(&asset_paths).await {
-        info!("loaded skybox from assets :)");
-        loaded
-    } else {
-        panic!("failed to download the necessary assets, to enable running this example offline, place the relevant assets in a folder called 'assets' next to the three-d source")
-    };
+    let mut loaded = three_d_asset::io::load_async(&asset_paths).await?;
+    info!("loaded skybox from assets :)");
+    Ok(loaded.deserialize("brown_photostudio_02_2k")?)
+}
 
-    let skybox = &mut Skybox::new_from_equirectangular(
-        &context,
-        &loaded.deserialize("brown_photostudio_02_2k").unwrap(),
-    );
-    let light = AmbientLight::new_with_environment(&context, 1.0, Srgba::WHITE, skybox.texture());
+/// buttons to save the bill of materials of the current configuration
+fn add_export_ui(ui: &mut Ui, product: &Product) {
+    ui.heading("Bill of materials");
+    ui.horizontal(|ui| {
+        if ui.button("BOM as CSV").clicked() {
+            if let Err(e) = save_file("bom.csv", &product.bom().to_csv()) {
+                error!("failed to export bom: {}", e);
+            }
+        }
+        if ui.button("BOM as JSON").clicked() {
+            let saved = product
+                .bom()
+                .to_json()
+                .map_err(crate::Error::from)
+                .and_then(|json| save_file("bom.json", &json));
+            if let Err(e) = saved {
+                error!("failed to export bom: {}", e);
+            }
+        }
+    });
+}
+
+/// the page url with `code` as its `config` query parameter
+#[cfg(target_arch = "wasm32")]
+fn share_link(code: &str) -> Option<String> {
+    let href = web_sys::window()?.location().href().ok()?;
+    let mut url = url::Url::parse(&href).ok()?;
+    let query: Vec<(String, String)> = url
+        .query_pairs()
+        .filter(|(key, _)| key != "config")
+        .map(|(key, value)| (key.into_owned(), value.into_owned()))
+        .collect();
+    url.query_pairs_mut()
+        .clear()
+        .extend_pairs(query)
+        .append_pair("config", code);
+    Some(url.into())
+}
+
+/// the configuration code, as a link on the web, to copy and share
+fn add_share_ui(ui: &mut Ui, product: &Product) {
+    ui.heading("Share");
+    let code = product.code();
+    #[cfg(target_arch = "wasm32")]
+    let code = share_link(&code).unwrap_or(code);
+    ui.add(TextEdit::singleline(&mut code.as_str()))
+        .on_hover_text("opens this configuration again");
+}
+
+/// undoes on Ctrl+Z and redoes on Ctrl+Y or Ctrl+Shift+Z, unless the gui used the key
+fn handle_history_keys(product: &mut Product, events: &mut [Event]) {
+    for event in events.iter_mut() {
+        if let Event::KeyPress {
+            kind,
+            modifiers,
+            handled,
+        } = event
+        {
+            if *handled || !(modifiers.ctrl || modifiers.command) {
+                continue;
+            }
+            match kind {
+                Key::Z if modifiers.shift => *handled = product.redo(),
+                Key::Z => *handled = product.undo(),
+                Key::Y => *handled = product.redo(),
+                _ => {}
+            }
+        }
+    }
+}
+
+pub async fn render(mut product: Product) -> crate::Result<()> {
+    let window = Window::new(WindowSettings {
+        title: "Product Config".to_string(),
+        ..Default::default()
+    })?;
+    let context = window.gl();
+
+    let light = match load_environment().await {
+        Ok(environment) => {
+            let skybox = Skybox::new_from_equirectangular(&context, &environment);
+            AmbientLight::new_with_environment(&context, 1.0, Srgba::WHITE, skybox.texture())
+        }
+        Err(e) => {
+            error!("failed to load skybox, using plain ambient light: {}", e);
+            AmbientLight::new(&context, 1.0, Srgba::WHITE)
+        }
+    };
 
     // let mut part = Component::placeholder();
     // part.init(&context);
@@ -41,7 +118,7 @@ pub async fn render(mut product: Product) {
     let mut camera = Camera::new_perspective(
         window.viewport(),
         vec3(0.0, 2410., 580.),
-        product.bbox().center(),
+        product.bbox()?.center(),
         vec3(0.0, 0.0, 1.0),
         degrees(45.0),
         1.0,
@@ -60,11 +137,20 @@ pub async fn render(mut product: Product) {
             frame_input.device_pixel_ratio,
             |gui_context| {
                 SidePanel::left("side_panel").show(gui_context, |ui| {
+                    product.add_history_controls(ui);
                     product.add_controls(ui);
+                    ui.separator();
+                    product.quote().add_ui(ui);
+                    ui.separator();
+                    add_export_ui(ui, &product);
+                    ui.separator();
+                    add_share_ui(ui, &product);
                 });
                 panel_width = gui_context.used_rect().width();
+                product.set_dragging(gui_context.input(|i| i.pointer.any_down()));
             },
         );
+        handle_history_keys(&mut product, &mut frame_input.events);
         product.update();
 
         let viewport = Viewport {
@@ -78,6 +164,7 @@ pub async fn render(mut product: Product) {
         control.handle_events(&mut camera, &mut frame_input.events);
 
         // let objects = skybox.into_iter().chain(product.objects());
+        // three-d draws transparent objects after the opaque ones, back to front
         let objects = product.objects();
         frame_input
             .screen()
@@ -87,4 +174,5 @@ pub async fn render(mut product: Product) {
 
         FrameOutput::default()
     });
+    Ok(())
 }
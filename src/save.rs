@@ -0,0 +1,38 @@
+//! Saving exported files, a download in the browser and a file write natively.
+#[cfg(not(target_arch = "wasm32"))]
+use log::info;
+
+use crate::Error;
+
+/// writes `contents` to `name` in the working directory
+#[cfg(not(target_arch = "wasm32"))]
+pub fn save_file(name: &str, contents: &str) -> crate::Result<()> {
+    std::fs::write(name, contents).map_err(Error::Io)?;
+    info!("saved {}", name);
+    Ok(())
+}
+
+/// offers `contents` as a file download named `name`
+#[cfg(target_arch = "wasm32")]
+pub fn save_file(name: &str, contents: &str) -> crate::Result<()> {
+    use wasm_bindgen::{JsCast, JsValue};
+
+    let js = |e: JsValue| Error::Browser(format!("{:?}", e).into());
+    let parts = js_sys::Array::of1(&JsValue::from_str(contents));
+    let blob = web_sys::Blob::new_with_str_sequence(&parts).map_err(js)?;
+    let url = web_sys::Url::create_object_url_with_blob(&blob).map_err(js)?;
+    let document = web_sys::window()
+        .and_then(|w| w.document())
+        .ok_or_else(|| Error::Browser("no document to download from".into()))?;
+    let anchor: web_sys::HtmlAnchorElement =
+        document
+            .create_element("a")
+            .map_err(js)?
+            .dyn_into()
+            .map_err(|_| Error::Browser("failed to create a download link".into()))?;
+    anchor.set_href(&url);
+    anchor.set_download(name);
+    anchor.click();
+    web_sys::Url::revoke_object_url(&url).map_err(js)?;
+    Ok(())
+}